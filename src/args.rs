@@ -0,0 +1,583 @@
+use chrono::{DateTime, NaiveDate, TimeDelta, Utc};
+use clap::{Parser, ValueEnum};
+use std::num::{NonZeroU32, NonZeroUsize};
+use std::path::PathBuf;
+
+pub const DEFAULT_PAGE_SIZE: usize = 10;
+
+#[derive(ValueEnum, Clone, Debug)]
+pub enum Format {
+    Table,
+    Json,
+    /// The same structure as `json`
+    Yaml,
+    /// Only the number of matches, ignoring the pagination
+    Count,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum GroupBy {
+    Repo,
+    State,
+    User,
+    Label,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum Bucket {
+    Day,
+    /// Weeks start on Monday
+    Week,
+    Month,
+}
+
+#[derive(ValueEnum, Clone, Debug)]
+pub enum SeriesFormat {
+    Table,
+    Json,
+    /// `bucket,count` lines with a header, e.g. for a plotting tool
+    Csv,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum Sort {
+    /// Most thumbs-up reactions first, issues synced before reactions were stored come last
+    PlusOneDesc,
+    /// Most often reopened first, counted from the state changes seen between syncs
+    ReopenedDesc,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default)]
+pub enum LabelMatch {
+    /// Issues with at least one of the labels
+    #[default]
+    Any,
+    /// Issues with every one of the labels
+    All,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default)]
+pub enum StorageKind {
+    /// RocksDB in the data directory
+    #[default]
+    Rocksdb,
+    /// A throwaway database removed on exit. kite_sql 0.1 has no in-memory engine,
+    /// so it is a RocksDB in the temp directory
+    Memory,
+}
+
+/// The `sort` values the GitHub issues endpoint accepts, always in descending order.
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum SortBy {
+    Created,
+    Updated,
+    Comments,
+}
+
+impl SortBy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SortBy::Created => "created",
+            SortBy::Updated => "updated",
+            SortBy::Comments => "comments",
+        }
+    }
+}
+
+#[derive(Parser, Debug)]
+pub struct UpdateArgs {
+    /// Stop paging once the sort field falls behind it, `--sort-by comments` pages to the end
+    #[clap(long)]
+    pub create_after: Option<DateTime<Utc>>,
+    /// Order in which GitHub pages the issues
+    #[clap(long, value_enum, default_value = "created")]
+    pub sort_by: SortBy,
+    /// Only sync the issues closed since the given time, which keeps closures current without a full sync
+    #[clap(long, conflicts_with = "create_after")]
+    pub closed_since: Option<DateTime<Utc>>,
+    /// Also store the discussions of each repo, requires GITHUB_TOKEN for the GraphQL API
+    #[clap(long, action, default_value = "false")]
+    pub with_discussions: bool,
+    /// Also store who wrote the first comment of each commented issue, one request per issue until it is known
+    #[clap(long, action, default_value = "false")]
+    pub with_first_response: bool,
+    /// Walk every issue of each repo and delete the stored ones GitHub no longer returns,
+    /// e.g. deleted or transferred issues. Costs a request per page of the whole history
+    #[clap(long, action, default_value = "false", conflicts_with_all = ["create_after", "closed_since"])]
+    pub prune_missing: bool,
+    /// Stop the first sync of a repo after about this many issues, the next update continues it
+    #[clap(long)]
+    pub first_sync_limit: Option<usize>,
+    /// Maximum number of requests to GitHub in flight at once
+    #[clap(long, default_value = "4")]
+    pub concurrency: NonZeroUsize,
+    /// How often a request is tried on timeouts, connection errors and 5xx answers
+    #[clap(long, default_value = "3")]
+    pub attempts: NonZeroU32,
+    /// Print how many requests were sent, how many the ETag cache answered and the bytes received
+    #[clap(long, action, default_value = "false")]
+    pub stats: bool,
+    /// Shell command run after the sync, reading a json summary from stdin, see `on_complete`
+    /// of the config file
+    #[clap(long)]
+    pub on_complete: Option<String>,
+    /// Do not show the progress of fetching
+    #[clap(long, action, default_value = "false")]
+    pub quiet: bool,
+    /// Keep the GitHub json of each issue, `reprocess` derives the columns from it again.
+    /// Issues synced without it drop their stored json
+    #[clap(long, action, default_value = "false")]
+    pub store_raw: bool,
+    /// Show a desktop notification for each new issue carrying the label
+    #[cfg(feature = "notify")]
+    #[clap(long)]
+    pub notify_label: Option<String>,
+}
+
+#[derive(Parser, Debug)]
+pub struct AddReposArgs {
+    /// File with one `owner/name` per line, lines starting with `#` are ignored
+    #[clap(long)]
+    pub file: PathBuf,
+}
+
+#[derive(Parser, Debug)]
+pub struct ImportJsonArgs {
+    /// Issues as served by GitHub, an array or the concatenated pages of `gh api --paginate`
+    pub path: PathBuf,
+    /// Repository the issues belong to, `owner/name`
+    #[clap(long)]
+    pub repo_name: String,
+    /// Keep the json of each issue, see `update --store-raw`
+    #[clap(long, action, default_value = "false")]
+    pub store_raw: bool,
+}
+
+#[derive(Parser, Debug)]
+pub struct ExportArgs {
+    /// Write one `owner__name.json` per repo into the directory instead of all issues to stdout
+    #[clap(long)]
+    pub output_dir: Option<PathBuf>,
+    /// Stream all issues to the file as JSON Lines in batches, for databases too large to hold in memory
+    #[clap(long, conflicts_with_all = ["output_dir", "pretty"])]
+    pub jsonl: Option<PathBuf>,
+    /// Continue an interrupted `--jsonl` export from the progress file next to it
+    #[clap(long, action, default_value = "false", requires = "jsonl")]
+    pub resume: bool,
+    #[clap(long, action, default_value = "false")]
+    pub pretty: bool,
+    /// Replace logins with stable pseudonyms like `user-7` and leave out notes, for sharing the data
+    #[clap(long, action, default_value = "false")]
+    pub anonymize: bool,
+}
+
+#[derive(Parser, Debug)]
+pub struct NoteArgs {
+    /// `owner/name`
+    pub repo_name: String,
+    pub number: u64,
+    /// Appended as a new line to the existing note
+    #[clap(required_unless_present = "clear")]
+    pub text: Option<String>,
+    /// Overwrite the existing note instead of appending to it
+    #[clap(long, action, default_value = "false")]
+    pub replace: bool,
+    /// Delete the note
+    #[clap(long, action, default_value = "false", conflicts_with_all = ["text", "replace"])]
+    pub clear: bool,
+}
+
+#[derive(Parser, Debug)]
+pub struct RefreshArgs {
+    /// `owner/name`
+    pub repo_name: String,
+    pub number: u64,
+    /// Keep the json of the issue, see `update --store-raw`
+    #[clap(long, action, default_value = "false")]
+    pub store_raw: bool,
+}
+
+#[derive(Parser, Debug)]
+pub struct ShowArgs {
+    /// `owner/name`
+    pub repo_name: String,
+    pub number: u64,
+    /// Print the markdown of the body as stored instead of formatting it
+    #[clap(long, action, default_value = "false")]
+    pub raw: bool,
+    /// Print directly instead of through `PAGER`
+    #[clap(long, action, default_value = "false")]
+    pub no_pager: bool,
+}
+
+#[derive(Parser, Debug)]
+pub struct RefreshStatesArgs {
+    /// Only issues updated since the given time, by default since the last `update` of each repo
+    #[clap(long)]
+    pub since: Option<DateTime<Utc>>,
+}
+
+#[derive(Parser, Debug)]
+pub struct RemoveIssuesArgs {
+    /// Remove every stored issue and pull request carrying the label
+    #[clap(long)]
+    pub label: String,
+}
+
+#[derive(Parser, Debug)]
+pub struct EditRepoArgs {
+    /// Tracked repo, `owner/name`
+    #[clap(long)]
+    pub from: String,
+    /// New name, `owner/name`
+    #[clap(long)]
+    pub to: String,
+}
+
+#[derive(Parser, Debug)]
+pub struct SetTokenEnvArgs {
+    /// Tracked repo, `owner/name`
+    pub repo_name: String,
+    /// Environment variable holding the token, e.g. `WORK_GITHUB_TOKEN`.
+    /// Without it the repo is synced with `GITHUB_TOKEN` again
+    pub token_env: Option<String>,
+}
+
+#[derive(Parser, Debug)]
+pub struct ImportStarredArgs {
+    #[clap(value_parser = parse_login)]
+    pub username: String,
+}
+
+#[derive(Parser, Debug, Clone)]
+pub struct FetchArgs {
+    #[clap(long)]
+    pub repo_name: Option<String>,
+    /// Ignore the default repo of `ISSUE_HUNTER_DEFAULT_REPO` or the config file
+    #[clap(long, action, default_value = "false", conflicts_with = "repo_name")]
+    pub all_repos: bool,
+    /// Only issues of the repos of the given owner
+    #[clap(long)]
+    pub owner: Option<String>,
+    /// Only issues of the repos carrying the GitHub topic, e.g. `database`
+    #[clap(long)]
+    pub topic: Option<String>,
+    /// Leave out the issues of the repo, may be given more than once
+    #[clap(long)]
+    pub exclude_repo: Vec<String>,
+    /// Only issues whose title or body contains the text, ignoring case
+    #[clap(long)]
+    pub contains: Option<String>,
+    /// Only issues created at or after the given time, see `--exclusive`
+    #[clap(long)]
+    pub create_after: Option<DateTime<Utc>>,
+    /// Leave out issues created exactly at the `--create-after` time
+    #[clap(long, action, default_value = "false", overrides_with = "inclusive")]
+    pub exclusive: bool,
+    /// Keep issues created exactly at the `--create-after` time, the default
+    #[clap(long, action, default_value = "false", overrides_with = "exclusive")]
+    pub inclusive: bool,
+    /// Only issues created before the given time
+    #[clap(long)]
+    pub create_before: Option<DateTime<Utc>>,
+    /// Only issues created since midnight UTC, midnight included
+    #[clap(long, action, default_value = "false")]
+    pub today: bool,
+    /// Only issues created on the given UTC day, e.g. `2025-02-13`
+    #[clap(long, conflicts_with_all = ["today", "create_after", "create_before", "min_age", "max_age"])]
+    pub created_on: Option<NaiveDate>,
+    /// Only issues at least this old, e.g. `7d`, `12h` or `2w`
+    #[clap(long, value_parser = parse_age)]
+    pub min_age: Option<TimeDelta>,
+    /// Only issues at most this old, e.g. `90d`
+    #[clap(long, value_parser = parse_age)]
+    pub max_age: Option<TimeDelta>,
+    /// Matches the label name ignoring case, unless `--label-exact` is given
+    #[clap(long)]
+    pub label_name: Option<String>,
+    #[clap(long, action, default_value = "false", requires = "label_name")]
+    pub label_exact: bool,
+    /// Use the closest label when `--label-name` matches none, if there is a single closest one
+    #[clap(long, action, default_value = "false", requires = "label_name")]
+    pub fuzzy: bool,
+    /// Only issues with the label of the id as shown by `labels`, may be given more than once
+    #[clap(long)]
+    pub label_id: Vec<u64>,
+    /// Whether issues need any or all of the `--label-id` labels
+    #[clap(long, value_enum, default_value_t, requires = "label_id")]
+    pub label_match: LabelMatch,
+    /// Only issues with a label whose description contains the text
+    #[clap(long)]
+    pub label_desc_contains: Option<String>,
+    /// Only issues in a milestone due before the given time
+    #[clap(long)]
+    pub milestone_due_before: Option<DateTime<Utc>>,
+    /// Only issues in a milestone due after the given time
+    #[clap(long)]
+    pub milestone_due_after: Option<DateTime<Utc>>,
+    /// Only the sub-issues of the issue with the given number, of `--repo-name` if given
+    #[clap(long)]
+    pub children_of: Option<u64>,
+    /// Nest sub-issues under their parents when both are in the output
+    #[clap(long, action, default_value = "false")]
+    pub tree: bool,
+    /// Only issues without any assignee, issues last synced before assignees were stored count as unassigned
+    #[clap(long, action, default_value = "false")]
+    pub unassigned: bool,
+    /// Only issues without any label
+    #[clap(long, action, default_value = "false", conflicts_with = "label_name")]
+    pub unlabeled: bool,
+    /// `open`, `closed`, a comma separated list of them, or `all`
+    #[clap(long)]
+    pub state: Option<String>,
+    /// Named set of filters, built-in `recent-open` and `stale-unlabeled` or one of the config file
+    #[clap(long)]
+    pub preset: Option<String>,
+    #[clap(long, default_value = "1")]
+    pub page: usize,
+    /// Issues per page, defaults to `default_page_size` of the config file or 10
+    #[clap(long)]
+    pub page_num: Option<usize>,
+    /// Cursor of keyset pagination, only issues created before it are fetched
+    #[clap(long, conflicts_with = "page")]
+    pub after_created: Option<DateTime<Utc>>,
+    /// Id of the last issue of the previous page, issues created at the `--after-created` time
+    /// with a lower id come next
+    #[clap(long, requires = "after_created")]
+    pub after_id: Option<u64>,
+    /// Order of the issues instead of the newest first
+    #[clap(long, value_enum, conflicts_with = "after_created")]
+    pub sort: Option<Sort>,
+    /// Cursor for batch consumers, only issues with a larger id, lowest id first.
+    /// Replaces the order, so it can not be combined with `--sort` or `--after-created`
+    #[clap(long, conflicts_with_all = ["sort", "after_created", "page"])]
+    pub since_id: Option<u64>,
+    #[clap(long, value_enum, default_value = "table")]
+    pub format: Format,
+    /// Print the number of issues per group instead of the issues, ignores the pagination
+    #[clap(long, value_enum)]
+    pub group_by: Option<GroupBy>,
+    /// Comma separated fields to keep in the json output
+    #[clap(long, value_delimiter = ',')]
+    pub fields: Vec<String>,
+    /// Pretty-print the json output instead of one line per result
+    #[clap(long, action, default_value = "false")]
+    pub pretty: bool,
+    /// Wrap the json output as `{"page": 1, "page_size": 10, "total": 42, "issues": [...]}`,
+    /// where `total` counts every issue matching the filters
+    #[clap(long, action, default_value = "false")]
+    pub with_meta: bool,
+    /// Prompt for the repo, state, label and date filters, prefilled with the given flags
+    #[clap(long, action, default_value = "false")]
+    pub interactive: bool,
+    /// Print the SQL query of the filters instead of running it
+    #[clap(long, action, default_value = "false")]
+    pub explain: bool,
+    /// Print the table directly instead of through `PAGER`
+    #[clap(long, action, default_value = "false")]
+    pub no_pager: bool,
+    /// Fail while an `update` is running instead of reading its half-synced state
+    #[clap(long, action, default_value = "false")]
+    pub respect_lock: bool,
+    /// Strip ANSI escapes and markdown emphasis from titles, the stored titles stay untouched
+    #[clap(long, action, default_value = "false")]
+    pub plain_titles: bool,
+    /// Cut titles in the table after this many characters, by default a third of the terminal width.
+    /// 0 keeps the full titles, as does the json output
+    #[clap(long)]
+    pub title_width: Option<usize>,
+    /// Render missing values as this token, e.g. `N/A`: empty table cells, and nulls of the json output
+    #[clap(long)]
+    pub null_as: Option<String>,
+    /// Tint the labels with the nearest terminal color of their GitHub color
+    #[clap(long, action, default_value = "false")]
+    pub color: bool,
+    /// Show the number of labels instead of their names, `--format json` has the full list
+    #[clap(
+        long,
+        alias = "compact-labels",
+        action,
+        default_value = "false",
+        conflicts_with = "color"
+    )]
+    pub label_summary: bool,
+    /// Leave the User column empty, skips one user lookup per issue
+    #[clap(long, action, default_value = "false")]
+    pub no_user: bool,
+    /// Leave the Labels column empty, skips one label join per issue
+    #[clap(long, action, default_value = "false")]
+    pub no_labels: bool,
+}
+
+#[derive(Parser, Debug)]
+pub struct SearchGithubArgs {
+    /// GitHub search syntax, e.g. `repo:rust-lang/rust label:bug state:open`
+    pub query: String,
+}
+
+#[derive(Parser, Debug, Clone)]
+pub struct DiscussionsArgs {
+    #[clap(long)]
+    pub repo_name: Option<String>,
+    #[clap(long, default_value = "1")]
+    pub page: usize,
+    /// Discussions per page, defaults to `default_page_size` of the config file or 10
+    #[clap(long)]
+    pub page_num: Option<usize>,
+}
+
+#[derive(Parser, Debug)]
+pub struct ReposArgs {
+    #[clap(long, value_enum, default_value = "table")]
+    pub format: Format,
+    /// Pretty-print the json output
+    #[clap(long, action, default_value = "false")]
+    pub pretty: bool,
+}
+
+#[derive(Parser, Debug)]
+pub struct AgeHistogramArgs {
+    #[clap(long)]
+    pub repo_name: Option<String>,
+    #[clap(long, value_enum, default_value = "table")]
+    pub format: Format,
+}
+
+#[derive(Parser, Debug)]
+pub struct TimeseriesArgs {
+    #[clap(long)]
+    pub repo_name: Option<String>,
+    #[clap(long, value_enum, default_value = "day")]
+    pub bucket: Bucket,
+    #[clap(long, value_enum, default_value = "table")]
+    pub format: SeriesFormat,
+}
+
+#[derive(Parser, Debug)]
+pub struct DiffArgs {
+    #[clap(long)]
+    pub repo_name: Option<String>,
+    #[clap(long, value_enum, default_value = "table")]
+    pub format: Format,
+    /// Take a new snapshot after reporting, so the next diff starts from now
+    #[clap(long, action, default_value = "false")]
+    pub save: bool,
+}
+
+#[derive(Parser, Debug)]
+pub struct CheckArgs {
+    /// Delete dangling label links and fetch missing users from GitHub
+    #[clap(long, action, default_value = "false")]
+    pub fix: bool,
+}
+
+/// Parses a duration like `30m`, `12h`, `7d` or `2w`.
+pub fn parse_age(input: &str) -> Result<TimeDelta, String> {
+    let split = input.len() - input.chars().last().map_or(0, char::len_utf8);
+    let (count, unit) = input.split_at(split);
+    let count = count
+        .parse::<i64>()
+        .map_err(|_| format!("'{}' is not a number followed by m, h, d or w", input))?;
+    let age = match unit {
+        "m" => TimeDelta::try_minutes(count),
+        "h" => TimeDelta::try_hours(count),
+        "d" => TimeDelta::try_days(count),
+        "w" => TimeDelta::try_weeks(count),
+        _ => return Err(format!("unknown unit '{}', expected m, h, d or w", unit)),
+    };
+
+    age.filter(|age| *age >= TimeDelta::zero())
+        .ok_or_else(|| format!("'{}' is out of range", input))
+}
+
+/// Parses a GitHub username, which only has letters, digits and hyphens, so it stays a single
+/// segment of the URL path.
+pub fn parse_login(input: &str) -> Result<String, String> {
+    if input.is_empty()
+        || input.len() > 39
+        || !input.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+    {
+        return Err(format!(
+            "'{}' is not a GitHub username, expected up to 39 letters, digits or hyphens",
+            input
+        ));
+    }
+
+    Ok(input.to_string())
+}
+
+impl FetchArgs {
+    pub fn page_size(&self) -> usize {
+        self.page_num.unwrap_or(DEFAULT_PAGE_SIZE)
+    }
+}
+
+impl DiscussionsArgs {
+    pub fn page_size(&self) -> usize {
+        self.page_num.unwrap_or(DEFAULT_PAGE_SIZE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_login_keeps_the_username_a_single_path_segment() {
+        assert_eq!(parse_login("octo-cat42").unwrap(), "octo-cat42");
+        for input in [
+            "",
+            "a/b",
+            "octocat?per_page=1",
+            "octocat#x",
+            "../user",
+            &"a".repeat(40),
+        ] {
+            assert!(parse_login(input).is_err(), "{} was accepted", input);
+        }
+    }
+
+    #[test]
+    fn parse_age_reads_each_unit() {
+        assert_eq!(parse_age("30m").unwrap(), TimeDelta::minutes(30));
+        assert_eq!(parse_age("12h").unwrap(), TimeDelta::hours(12));
+        assert_eq!(parse_age("7d").unwrap(), TimeDelta::days(7));
+        assert_eq!(parse_age("2w").unwrap(), TimeDelta::weeks(2));
+        assert_eq!(parse_age("0d").unwrap(), TimeDelta::zero());
+    }
+
+    #[test]
+    fn parse_age_rejects_missing_and_unknown_units() {
+        assert_eq!(
+            parse_age("30").unwrap_err(),
+            "unknown unit '0', expected m, h, d or w"
+        );
+        assert_eq!(
+            parse_age("3y").unwrap_err(),
+            "unknown unit 'y', expected m, h, d or w"
+        );
+        for input in ["", "d", "xd", "1.5d", "🚀"] {
+            assert!(parse_age(input).is_err(), "{} was accepted", input);
+        }
+    }
+
+    #[test]
+    fn parse_age_rejects_overflowing_and_negative_ages() {
+        assert_eq!(
+            parse_age("99999999999999w").unwrap_err(),
+            "'99999999999999w' is out of range"
+        );
+        assert!(parse_age("99999999999999999999d").is_err());
+        assert_eq!(parse_age("-1d").unwrap_err(), "'-1d' is out of range");
+    }
+
+    #[test]
+    fn discussions_page_size_falls_back_to_the_default() {
+        let args = DiscussionsArgs::parse_from(["discussions"]);
+        assert_eq!(args.page_num, None);
+        assert_eq!(args.page_size(), DEFAULT_PAGE_SIZE);
+
+        let args = DiscussionsArgs::parse_from(["discussions", "--page-num", "3"]);
+        assert_eq!(args.page_size(), 3);
+    }
+}
@@ -0,0 +1,605 @@
+use crate::error::Error;
+use crate::model::{
+    Checkpoint, Discussion, ETag, FirstResponse, Issue, IssueAssignee, IssueLabelLink, IssueLock,
+    IssueMilestoneLink, Label, Milestone, PullRequest, Reactions, Reopens, Repo, RepoTopic, User,
+};
+use crate::{escape_sql_string, SqlBase};
+use chrono::{DateTime, Utc};
+use kite_sql::db::ResultIter;
+
+fn optional_string(value: &Option<String>) -> String {
+    value
+        .as_ref()
+        .map(|s| format!("'{}'", escape_sql_string(s)))
+        .unwrap_or("null".to_string())
+}
+
+fn optional_datetime(datetime: Option<DateTime<Utc>>) -> String {
+    datetime
+        .map(|datetime| format!("'{}'", datetime.format("%Y-%m-%d %H:%M:%S")))
+        .unwrap_or("null".to_string())
+}
+
+pub trait Bean {
+    fn insert(&self, database: &SqlBase) -> Result<(), Error>;
+    fn delete(&self, database: &SqlBase) -> Result<(), Error>;
+}
+
+impl Bean for Label {
+    fn insert(&self, database: &SqlBase) -> Result<(), Error> {
+        database
+            .run(format!(
+                "insert overwrite labels values({}, '{}', {}, {});",
+                self.id,
+                escape_sql_string(&self.name),
+                optional_string(&self.description),
+                optional_string(&self.color),
+            ))?
+            .done()?;
+
+        Ok(())
+    }
+
+    fn delete(&self, database: &SqlBase) -> Result<(), Error> {
+        database
+            .run(format!("delete from labels where id = {};", self.id))?
+            .done()?;
+
+        Ok(())
+    }
+}
+
+impl Bean for ETag {
+    fn insert(&self, database: &SqlBase) -> Result<(), Error> {
+        database
+            .run(format!(
+                "insert overwrite etags values('{}', '{}', '{}');",
+                escape_sql_string(&self.url),
+                escape_sql_string(&self.etag),
+                self.oldest_created.format("%Y-%m-%d %H:%M:%S"),
+            ))?
+            .done()?;
+
+        Ok(())
+    }
+
+    fn delete(&self, database: &SqlBase) -> Result<(), Error> {
+        database
+            .run(format!(
+                "delete from etags where url = '{}';",
+                escape_sql_string(&self.url)
+            ))?
+            .done()?;
+
+        Ok(())
+    }
+}
+
+impl Bean for Checkpoint {
+    fn insert(&self, database: &SqlBase) -> Result<(), Error> {
+        database
+            .run(format!(
+                "insert overwrite checkpoints values('{}', '{}', {}, {});",
+                escape_sql_string(&self.repo_name),
+                escape_sql_string(&self.sort_by),
+                self.page,
+                optional_datetime(self.oldest_created),
+            ))?
+            .done()?;
+
+        Ok(())
+    }
+
+    fn delete(&self, database: &SqlBase) -> Result<(), Error> {
+        database
+            .run(format!(
+                "delete from checkpoints where repo_name = '{}';",
+                escape_sql_string(&self.repo_name)
+            ))?
+            .done()?;
+
+        Ok(())
+    }
+}
+
+impl Bean for IssueLabelLink {
+    fn insert(&self, database: &SqlBase) -> Result<(), Error> {
+        database
+            .run(format!(
+                "insert overwrite issue_labels values({}, {});",
+                self.issue_id, self.label_id
+            ))?
+            .done()?;
+
+        Ok(())
+    }
+
+    fn delete(&self, database: &SqlBase) -> Result<(), Error> {
+        database
+            .run(format!(
+                "delete from issue_labels where issue_id = {} and label_id = {};",
+                self.issue_id, self.label_id
+            ))?
+            .done()?;
+
+        Ok(())
+    }
+}
+
+impl Bean for Milestone {
+    fn insert(&self, database: &SqlBase) -> Result<(), Error> {
+        database
+            .run(format!(
+                "insert overwrite milestones values({}, {}, '{}', '{}', '{}', {});",
+                self.id,
+                self.number,
+                escape_sql_string(&self.title),
+                escape_sql_string(&self.state),
+                escape_sql_string(&self.repo_name),
+                optional_datetime(self.due_on),
+            ))?
+            .done()?;
+
+        Ok(())
+    }
+
+    fn delete(&self, database: &SqlBase) -> Result<(), Error> {
+        database
+            .run(format!("delete from milestones where id = {};", self.id))?
+            .done()?;
+
+        Ok(())
+    }
+}
+
+impl Bean for IssueAssignee {
+    fn insert(&self, database: &SqlBase) -> Result<(), Error> {
+        database
+            .run(format!(
+                "insert overwrite assignees values({}, {});",
+                self.issue_id, self.user_id
+            ))?
+            .done()?;
+
+        Ok(())
+    }
+
+    fn delete(&self, database: &SqlBase) -> Result<(), Error> {
+        database
+            .run(format!(
+                "delete from assignees where issue_id = {} and user_id = {};",
+                self.issue_id, self.user_id
+            ))?
+            .done()?;
+
+        Ok(())
+    }
+}
+
+impl Bean for IssueLock {
+    fn insert(&self, database: &SqlBase) -> Result<(), Error> {
+        database
+            .run(format!(
+                "insert overwrite locks values({}, {});",
+                self.issue_id,
+                optional_string(&self.reason),
+            ))?
+            .done()?;
+
+        Ok(())
+    }
+
+    fn delete(&self, database: &SqlBase) -> Result<(), Error> {
+        database
+            .run(format!(
+                "delete from locks where issue_id = {};",
+                self.issue_id
+            ))?
+            .done()?;
+
+        Ok(())
+    }
+}
+
+impl Bean for FirstResponse {
+    fn insert(&self, database: &SqlBase) -> Result<(), Error> {
+        database
+            .run(format!(
+                "insert overwrite first_responses values({}, {});",
+                self.issue_id,
+                self.user_id
+                    .map(|user_id| user_id.to_string())
+                    .unwrap_or("null".to_string()),
+            ))?
+            .done()?;
+
+        Ok(())
+    }
+
+    fn delete(&self, database: &SqlBase) -> Result<(), Error> {
+        database
+            .run(format!(
+                "delete from first_responses where issue_id = {};",
+                self.issue_id
+            ))?
+            .done()?;
+
+        Ok(())
+    }
+}
+
+impl Bean for IssueMilestoneLink {
+    fn insert(&self, database: &SqlBase) -> Result<(), Error> {
+        database
+            .run(format!(
+                "insert overwrite issue_milestones values({}, {});",
+                self.issue_id, self.milestone_id
+            ))?
+            .done()?;
+
+        Ok(())
+    }
+
+    fn delete(&self, database: &SqlBase) -> Result<(), Error> {
+        database
+            .run(format!(
+                "delete from issue_milestones where issue_id = {};",
+                self.issue_id
+            ))?
+            .done()?;
+
+        Ok(())
+    }
+}
+
+impl Bean for Discussion {
+    fn insert(&self, database: &SqlBase) -> Result<(), Error> {
+        database
+            .run(format!(
+                "insert overwrite discussions values({}, {}, '{}', '{}', {}, {}, '{}');",
+                self.id,
+                self.number,
+                escape_sql_string(&self.title),
+                escape_sql_string(&self.repo_name),
+                optional_string(&self.author),
+                optional_string(&self.category),
+                self.created_at.format("%Y-%m-%d %H:%M:%S"),
+            ))?
+            .done()?;
+
+        Ok(())
+    }
+
+    fn delete(&self, database: &SqlBase) -> Result<(), Error> {
+        database
+            .run(format!("delete from discussions where id = {};", self.id))?
+            .done()?;
+
+        Ok(())
+    }
+}
+
+impl Bean for Reactions {
+    fn insert(&self, database: &SqlBase) -> Result<(), Error> {
+        database
+            .run(format!(
+                "insert overwrite reactions values({}, {}, {}, {}, {}, {}, {}, {}, {});",
+                self.issue_id,
+                self.plus_one,
+                self.minus_one,
+                self.laugh,
+                self.hooray,
+                self.confused,
+                self.heart,
+                self.rocket,
+                self.eyes,
+            ))?
+            .done()?;
+
+        Ok(())
+    }
+
+    fn delete(&self, database: &SqlBase) -> Result<(), Error> {
+        database
+            .run(format!(
+                "delete from reactions where issue_id = {};",
+                self.issue_id
+            ))?
+            .done()?;
+
+        Ok(())
+    }
+}
+
+impl Bean for Reopens {
+    fn insert(&self, database: &SqlBase) -> Result<(), Error> {
+        database
+            .run(format!(
+                "insert overwrite reopens values({}, {});",
+                self.issue_id, self.reopened_count
+            ))?
+            .done()?;
+
+        Ok(())
+    }
+
+    fn delete(&self, database: &SqlBase) -> Result<(), Error> {
+        database
+            .run(format!(
+                "delete from reopens where issue_id = {};",
+                self.issue_id
+            ))?
+            .done()?;
+
+        Ok(())
+    }
+}
+
+impl Bean for Repo {
+    fn insert(&self, database: &SqlBase) -> Result<(), Error> {
+        database
+            .run(format!(
+                "insert overwrite repos values('{}', '{}', {}, {}, {}, {}, {});",
+                self.owner_name,
+                self.name,
+                self.default_branch
+                    .as_ref()
+                    .map(|s| format!("'{}'", escape_sql_string(s)))
+                    .unwrap_or("null".to_string()),
+                optional_datetime(self.last_updated),
+                self.archived,
+                self.first_sync_page
+                    .map(|page| page.to_string())
+                    .unwrap_or("null".to_string()),
+                optional_string(&self.token_env),
+            ))?
+            .done()?;
+
+        Ok(())
+    }
+
+    fn delete(&self, database: &SqlBase) -> Result<(), Error> {
+        database
+            .run(format!(
+                "delete from repos where owner_name = '{}' and name = '{}';",
+                self.owner_name, self.name
+            ))?
+            .done()?;
+        database
+            .run(format!(
+                "delete from issues where repo_name = '{}';",
+                self.full_name()
+            ))?
+            .done()?;
+        database
+            .run(format!(
+                "delete from prs where repo_name = '{}';",
+                self.full_name()
+            ))?
+            .done()?;
+        database
+            .run(format!(
+                "delete from discussions where repo_name = '{}';",
+                self.full_name()
+            ))?
+            .done()?;
+        database
+            .run(format!(
+                "delete from checkpoints where repo_name = '{}';",
+                self.full_name()
+            ))?
+            .done()?;
+        database
+            .run(format!(
+                "delete from repo_topics where repo_name = '{}';",
+                self.full_name()
+            ))?
+            .done()?;
+
+        Ok(())
+    }
+}
+
+impl Bean for RepoTopic {
+    fn insert(&self, database: &SqlBase) -> Result<(), Error> {
+        database
+            .run(format!(
+                "insert overwrite repo_topics values('{}', '{}');",
+                escape_sql_string(&self.repo_name),
+                escape_sql_string(&self.topic),
+            ))?
+            .done()?;
+
+        Ok(())
+    }
+
+    fn delete(&self, database: &SqlBase) -> Result<(), Error> {
+        database
+            .run(format!(
+                "delete from repo_topics where repo_name = '{}' and topic = '{}';",
+                escape_sql_string(&self.repo_name),
+                escape_sql_string(&self.topic)
+            ))?
+            .done()?;
+
+        Ok(())
+    }
+}
+
+impl Bean for User {
+    fn insert(&self, database: &SqlBase) -> Result<(), Error> {
+        database
+            .run(format!(
+                "insert overwrite users values({}, '{}');",
+                self.id, self.login
+            ))?
+            .done()?;
+
+        Ok(())
+    }
+
+    fn delete(&self, database: &SqlBase) -> Result<(), Error> {
+        database
+            .run(format!("delete from users where id = {};", self.id))?
+            .done()?;
+
+        Ok(())
+    }
+}
+
+impl Bean for Issue {
+    fn insert(&self, database: &SqlBase) -> Result<(), Error> {
+        self.record_reopen("issues", database)?;
+        database
+            .run(format!(
+                "insert overwrite issues values({}, {}, '{}', '{}', '{}', {}, '{}', {}, {}, {}, {});",
+                self.id,
+                self.number,
+                escape_sql_string(&self.title),
+                self.state,
+                self.repo_name,
+                self.user.id,
+                self.created_at.format("%Y-%m-%d %H:%M:%S"),
+                optional_datetime(self.closed_at),
+                optional_string(&self.body),
+                optional_string(&self.raw),
+                optional_string(&self.parent_issue_url),
+            ))?
+            .done()?;
+        self.insert_user_and_labels(database)?;
+
+        Ok(())
+    }
+
+    fn delete(&self, database: &SqlBase) -> Result<(), Error> {
+        database
+            .run(format!("delete from issues where id = {};", self.id))?
+            .done()?;
+        database
+            .run(format!(
+                "delete from issue_labels where issue_id = {};",
+                self.id
+            ))?
+            .done()?;
+        database
+            .run(format!(
+                "delete from reactions where issue_id = {};",
+                self.id
+            ))?
+            .done()?;
+        database
+            .run(format!("delete from reopens where issue_id = {};", self.id))?
+            .done()?;
+        database
+            .run(format!("delete from notes where issue_id = {};", self.id))?
+            .done()?;
+        database
+            .run(format!(
+                "delete from issue_milestones where issue_id = {};",
+                self.id
+            ))?
+            .done()?;
+        database
+            .run(format!("delete from locks where issue_id = {};", self.id))?
+            .done()?;
+        database
+            .run(format!(
+                "delete from assignees where issue_id = {};",
+                self.id
+            ))?
+            .done()?;
+        database
+            .run(format!(
+                "delete from first_responses where issue_id = {};",
+                self.id
+            ))?
+            .done()?;
+
+        Ok(())
+    }
+}
+
+impl Bean for PullRequest {
+    fn insert(&self, database: &SqlBase) -> Result<(), Error> {
+        let issue = &self.issue;
+        issue.record_reopen("prs", database)?;
+        database
+            .run(format!(
+                "insert overwrite prs values({}, {}, '{}', '{}', '{}', {}, '{}', {}, {}, {}, {}, {}, {}, {});",
+                issue.id,
+                issue.number,
+                escape_sql_string(&issue.title),
+                issue.state,
+                issue.repo_name,
+                issue.user.id,
+                issue.created_at.format("%Y-%m-%d %H:%M:%S"),
+                self.merged,
+                optional_string(&self.head),
+                optional_string(&self.base),
+                optional_datetime(issue.closed_at),
+                optional_string(&issue.body),
+                optional_string(&issue.raw),
+                optional_string(&issue.parent_issue_url),
+            ))?
+            .done()?;
+        issue.insert_user_and_labels(database)?;
+
+        Ok(())
+    }
+
+    fn delete(&self, database: &SqlBase) -> Result<(), Error> {
+        database
+            .run(format!("delete from prs where id = {};", self.issue.id))?
+            .done()?;
+        database
+            .run(format!(
+                "delete from issue_labels where issue_id = {};",
+                self.issue.id
+            ))?
+            .done()?;
+        database
+            .run(format!(
+                "delete from reactions where issue_id = {};",
+                self.issue.id
+            ))?
+            .done()?;
+        database
+            .run(format!(
+                "delete from reopens where issue_id = {};",
+                self.issue.id
+            ))?
+            .done()?;
+        database
+            .run(format!(
+                "delete from notes where issue_id = {};",
+                self.issue.id
+            ))?
+            .done()?;
+        database
+            .run(format!(
+                "delete from issue_milestones where issue_id = {};",
+                self.issue.id
+            ))?
+            .done()?;
+        database
+            .run(format!(
+                "delete from locks where issue_id = {};",
+                self.issue.id
+            ))?
+            .done()?;
+        database
+            .run(format!(
+                "delete from assignees where issue_id = {};",
+                self.issue.id
+            ))?
+            .done()?;
+        database
+            .run(format!(
+                "delete from first_responses where issue_id = {};",
+                self.issue.id
+            ))?
+            .done()?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,3967 @@
+use crate::args::{
+    AddReposArgs, AgeHistogramArgs, Bucket, DiscussionsArgs, EditRepoArgs, FetchArgs, GroupBy,
+    ImportJsonArgs, ImportStarredArgs, LabelMatch, NoteArgs, RefreshArgs, RefreshStatesArgs,
+    SearchGithubArgs, Sort, SortBy, StorageKind, TimeseriesArgs, UpdateArgs,
+};
+use crate::bean::Bean;
+use crate::error::Error;
+use crate::model::{
+    ApiError, AuthenticatedUser, Change, Checkpoint, Comment, CrossRepoIssue, Discussion,
+    DiscussionsData, ETag, FirstResponse, GraphQlResponse, Issue, IssueChange, IssueLabelLink,
+    Label, Problem, PullRequest, PullRequestDetail, PullRequestRef, RateLimits, Repo, RepoMetadata,
+    RepoTopic, SearchResult, User,
+};
+use crate::{escape_like, escape_sql_string, id_value, SqlBase};
+use chrono::{DateTime, Datelike, Months, NaiveDate, NaiveTime, TimeDelta, TimeZone, Utc};
+use futures::future;
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use kite_sql::db::{DataBaseBuilder, ResultIter};
+use kite_sql::errors::DatabaseError;
+use kite_sql::types::value::DataValue;
+use reqwest::header::{ETAG, IF_NONE_MATCH};
+use reqwest::{RequestBuilder, Response, StatusCode};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::cmp::min;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::Duration;
+use tokio::sync::Semaphore;
+
+pub struct Client {
+    pub client: reqwest::Client,
+    pub database: SqlBase,
+    pub token: Option<String>,
+    /// `https://api.github.com`, pointed elsewhere by tests
+    api_url: String,
+    limiter: Semaphore,
+    attempts: u32,
+    stats: RequestCounters,
+    /// Declared after `database`, so the database is closed before its directory goes
+    _temp_dir: Option<TempDir>,
+}
+
+#[derive(Default)]
+struct RequestCounters {
+    requests: AtomicUsize,
+    not_modified: AtomicUsize,
+    bytes: AtomicU64,
+}
+
+/// Counts of the requests a client sent, retries included.
+#[derive(Debug, Clone, Copy)]
+pub struct RequestStats {
+    pub requests: usize,
+    /// Answers served from the ETag cache
+    pub not_modified: usize,
+    /// Body bytes read after decompression
+    pub bytes: u64,
+}
+
+/// A directory removed with everything in it when dropped.
+struct TempDir(PathBuf);
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+const GITHUB_API_URL: &str = "https://api.github.com";
+pub const DEFAULT_CONCURRENCY: usize = 4;
+pub const DEFAULT_ATTEMPTS: u32 = 3;
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(500);
+
+/// Upper bounds in days of the `age-histogram` buckets, older issues fall into a last bucket.
+const AGE_BUCKETS: [(i64, &str); 4] = [(1, "<1d"), (7, "1-7d"), (30, "7-30d"), (90, "30-90d")];
+
+/// The search API serves 100 items per page and no more than 1000 results per query.
+const SEARCH_PAGE_SIZE: usize = 100;
+const SEARCH_MAX_RESULTS: usize = 1000;
+/// No issue was created before GitHub launched, where the first window of a split search starts.
+const SEARCH_CREATED_FROM: DateTime<Utc> =
+    DateTime::from_timestamp_nanos(1_199_145_600_000_000_000);
+/// The search API allows far fewer requests per minute than the rest of the API, a search it
+/// rejects is tried again once the limit resets, unless that takes longer than this.
+const SEARCH_MAX_WAIT: Duration = Duration::from_secs(60);
+const SEARCH_ATTEMPTS: u32 = 3;
+
+const ISSUE_STATES: [&str; 2] = ["open", "closed"];
+
+/// Returns the states to filter on, an empty list means no filtering.
+fn parse_states(input: &str) -> Result<Vec<&str>, Error> {
+    let mut states = Vec::new();
+
+    for token in input.split(',').map(str::trim) {
+        if token == "all" {
+            return Ok(Vec::new());
+        }
+        if !ISSUE_STATES.contains(&token) {
+            return Err(Error::Parse(format!(
+                "invalid state: '{}', expected one of: open, closed, all",
+                token
+            )));
+        }
+        if !states.contains(&token) {
+            states.push(token);
+        }
+    }
+
+    Ok(states)
+}
+
+/// Turns unsuccessful responses into errors, telling rate limiting apart from other failures.
+fn check_response(response: Response) -> Result<Response, Error> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let header = |name: &str| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<i64>().ok())
+    };
+    if (status == StatusCode::FORBIDDEN || status == StatusCode::TOO_MANY_REQUESTS)
+        && header("x-ratelimit-remaining") == Some(0)
+    {
+        let reset = header("x-ratelimit-reset")
+            .and_then(|timestamp| DateTime::from_timestamp(timestamp, 0))
+            .map(|reset| reset.to_string())
+            .unwrap_or("unknown".to_string());
+        return Err(Error::RateLimited(reset));
+    }
+
+    Err(Error::Status(status))
+}
+
+/// How long to wait before trying a search again, from `retry-after` or the reset of the search
+/// rate limit. `None` when the response is no rate limit or the wait exceeds `SEARCH_MAX_WAIT`.
+fn search_retry_wait(response: &Response) -> Option<Duration> {
+    let status = response.status();
+    if status != StatusCode::FORBIDDEN && status != StatusCode::TOO_MANY_REQUESTS {
+        return None;
+    }
+    let header = |name: &str| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<i64>().ok())
+    };
+    let seconds = match header("retry-after") {
+        Some(seconds) => seconds,
+        None if header("x-ratelimit-remaining") == Some(0) => {
+            header("x-ratelimit-reset")? - Utc::now().timestamp() + 1
+        }
+        None => return None,
+    };
+    let wait = Duration::from_secs(seconds.max(0) as u64);
+
+    (wait <= SEARCH_MAX_WAIT).then_some(wait)
+}
+
+/// Values per `in (...)` list, longer lists are split into several. kite_sql slows down
+/// quadratically on a single long list and evaluates it recursively, so a few hundred values
+/// already overflow the stack of a spawned thread.
+const IN_LIST_CHUNK_SIZE: usize = 100;
+
+/// Restricts a query to the given ids, an empty list matches nothing.
+fn id_condition(ids: &[String]) -> String {
+    if ids.is_empty() {
+        return " and 1 = 0".to_string();
+    }
+    format!(" and {}", in_lists("id", ids, false))
+}
+
+/// `column in (...)` split into lists of `IN_LIST_CHUNK_SIZE` values joined with `or`,
+/// or `column not in (...)` joined with `and` when `negated`. `values` must not be empty.
+fn in_lists(column: &str, values: &[String], negated: bool) -> String {
+    let (operator, separator) = if negated {
+        ("not in", " and ")
+    } else {
+        ("in", " or ")
+    };
+    let lists = values
+        .chunks(IN_LIST_CHUNK_SIZE)
+        .map(|chunk| format!("{} {} ({})", column, operator, chunk.join(", ")))
+        .collect::<Vec<_>>();
+    join_balanced(&lists, separator)
+}
+
+/// Joins the conditions pairwise into a balanced tree, kite_sql also evaluates a flat chain
+/// of `or`s recursively.
+fn join_balanced(conditions: &[String], separator: &str) -> String {
+    match conditions {
+        [condition] => condition.clone(),
+        _ => {
+            let (left, right) = conditions.split_at(conditions.len() / 2);
+            format!(
+                "({}{}{})",
+                join_balanced(left, separator),
+                separator,
+                join_balanced(right, separator)
+            )
+        }
+    }
+}
+
+/// The conditions of the filters that need no lookups, so the same arguments always give the same text.
+/// `now` anchors `--today` and the ages.
+fn filter_conditions(args: &FetchArgs, now: DateTime<Utc>) -> Result<String, Error> {
+    let mut query = "1 = 1".to_string();
+
+    if let Some(repo_name) = &args.repo_name {
+        query.push_str(&format!(" and repo_name like '{}'", repo_name));
+    }
+    if let Some(number) = args.children_of {
+        // The parent may live in another repo, which `--repo-name` narrows down
+        let parent = match &args.repo_name {
+            Some(repo_name) => format!("%/repos/{}/issues/{}", escape_like(repo_name), number),
+            None => format!("%/issues/{}", number),
+        };
+        query.push_str(&format!(
+            " and parent_issue_url like '{}' escape '\\'",
+            parent
+        ));
+    }
+    if let Some(owner) = &args.owner {
+        let owner = escape_like(owner);
+        query.push_str(&format!(" and repo_name like '{}/%' escape '\\'", owner));
+    }
+    for repo_name in &args.exclude_repo {
+        query.push_str(&format!(
+            " and repo_name not like '{}' escape '\\'",
+            escape_like(repo_name)
+        ));
+    }
+    if args.today {
+        query.push_str(&format!(
+            " and created_at >= '{} 00:00:00'",
+            now.date_naive().format("%Y-%m-%d")
+        ));
+    } else if let Some(create_after) = args.create_after {
+        query.push_str(&format!(
+            " and created_at {} '{}'",
+            if args.exclusive { ">" } else { ">=" },
+            create_after.format("%Y-%m-%d %H:%M:%S")
+        ));
+    }
+    if let Some(create_before) = args.create_before {
+        query.push_str(&format!(
+            " and created_at < '{}'",
+            create_before.format("%Y-%m-%d %H:%M:%S")
+        ));
+    }
+    if let (Some(min_age), Some(max_age)) = (args.min_age, args.max_age) {
+        if min_age > max_age {
+            return Err(Error::Parse(
+                "--min-age must not be larger than --max-age".to_string(),
+            ));
+        }
+    }
+    if let Some(min_age) = args.min_age {
+        query.push_str(&format!(
+            " and created_at <= '{}'",
+            (now - min_age).format("%Y-%m-%d %H:%M:%S")
+        ));
+    }
+    if let Some(max_age) = args.max_age {
+        query.push_str(&format!(
+            " and created_at >= '{}'",
+            (now - max_age).format("%Y-%m-%d %H:%M:%S")
+        ));
+    }
+    if let Some(created_on) = args.created_on {
+        let start = created_on.and_time(NaiveTime::MIN);
+        query.push_str(&format!(
+            " and created_at >= '{}' and created_at < '{}'",
+            start.format("%Y-%m-%d %H:%M:%S"),
+            (start + TimeDelta::days(1)).format("%Y-%m-%d %H:%M:%S")
+        ));
+    }
+    if let Some(states) = args.state.as_deref().map(parse_states).transpose()? {
+        if !states.is_empty() {
+            let states = states
+                .iter()
+                .map(|state| format!("'{}'", state))
+                .collect::<Vec<_>>()
+                .join(", ");
+            query.push_str(&format!(" and state in ({})", states));
+        }
+    }
+    if args.unlabeled {
+        query.push_str(" and id not in (select issue_id from issue_labels)");
+    }
+
+    Ok(query)
+}
+
+/// Where `--since-id` or `--after-created` continue, empty without a cursor.
+fn cursor_condition(args: &FetchArgs) -> String {
+    if let Some(since_id) = args.since_id {
+        format!(" and id > {}", since_id)
+    } else if let Some(cursor) = args.after_created {
+        // Seek past the cursor instead of skipping rows, which stays fast on deep pages
+        let cursor = cursor.format("%Y-%m-%d %H:%M:%S");
+        match args.after_id {
+            // Issues created at the cursor time continue in the order of the tiebreaker
+            Some(after_id) => format!(
+                " and (created_at < '{0}' or (created_at = '{0}' and id < {1}))",
+                cursor, after_id
+            ),
+            None => format!(" and created_at < '{}'", cursor),
+        }
+    } else {
+        String::new()
+    }
+}
+
+/// Builds the filtered and paginated query over `issues` or `prs`, which share their columns,
+/// from the conditions of `Client::filter_clause`. `tiebreaker` adds `id` as second sort key.
+fn fetch_query(table: &str, filter: &str, args: &FetchArgs, tiebreaker: bool) -> String {
+    let (from, order) = match args.sort {
+        // None of the filters refer to `issue_id`, so the join leaves them unambiguous.
+        Some(Sort::PlusOneDesc) => (
+            format!("{0}.* from {0} left join reactions on id = issue_id", table),
+            "plus_one desc",
+        ),
+        Some(Sort::ReopenedDesc) => (
+            format!("{0}.* from {0} left join reopens on id = issue_id", table),
+            "reopened_count desc",
+        ),
+        None => (format!("* from {}", table), "created_at desc"),
+    };
+    let tiebreaker = if tiebreaker { ", id desc" } else { "" };
+    let mut query = format!("select {} where {}{}", from, filter, cursor_condition(args));
+
+    if args.since_id.is_some() {
+        // Ids are unique, so the order is total without a tiebreaker
+        query.push_str(&format!(" order by id asc limit {};", args.page_size()));
+    } else if args.after_created.is_some() {
+        query.push_str(&format!(
+            " order by created_at desc{} limit {};",
+            tiebreaker,
+            args.page_size()
+        ));
+    } else {
+        query.push_str(&format!(
+            " order by {}{} limit {} offset {};",
+            order,
+            tiebreaker,
+            args.page_size(),
+            (args.page - 1) * args.page_size()
+        ));
+    }
+
+    query
+}
+
+/// kite_sql names neither the query nor the cause when it rejects one.
+fn query_error(err: DatabaseError, query: &str) -> Error {
+    let hint = if query.matches(',').count() > IN_LIST_CHUNK_SIZE {
+        "Broad label, milestone or assignee filters resolve to long id lists, narrowing them may help"
+    } else {
+        "This combination of filters may not be supported"
+    };
+    Error::Query(err, hint.to_string())
+}
+
+/// RocksDB refuses a directory another process has open with an IO error on its `LOCK` file,
+/// which kite_sql only passes on as text.
+fn storage_error(err: DatabaseError, path: &Path) -> Error {
+    let message = err.to_string();
+    if message.contains("lock file") || message.contains("/LOCK") {
+        return Error::DatabaseLocked(path.display().to_string());
+    }
+    err.into()
+}
+
+/// The labels closest to the name, if within a third of its length, but at least 2 edits.
+/// Repos sharing a label name yield it once.
+fn label_suggestions<'a>(label_name: &str, names: impl Iterator<Item = &'a String>) -> Vec<String> {
+    let label_name = label_name.to_lowercase();
+    let max_distance = (label_name.chars().count() / 3).max(2);
+    let mut suggestions = names
+        .map(|name| (strsim::levenshtein(&label_name, &name.to_lowercase()), name))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .collect::<Vec<_>>();
+    suggestions.sort();
+    let Some(closest) = suggestions.first().map(|(distance, _)| *distance) else {
+        return Vec::new();
+    };
+    let mut names = suggestions
+        .into_iter()
+        .take_while(|(distance, _)| *distance == closest)
+        .map(|(_, name)| name.clone())
+        .collect::<Vec<_>>();
+    names.dedup();
+
+    names
+}
+
+/// Characters of the body quoted when a payload is neither the expected shape nor a GitHub error.
+const PAYLOAD_SNIPPET_LEN: usize = 200;
+
+fn unexpected_payload(body: &str, err: serde_json::Error) -> Error {
+    if let Ok(api_error) = serde_json::from_str::<ApiError>(body) {
+        return Error::Parse(match api_error.documentation_url {
+            Some(url) => format!("GitHub: {} (see {})", api_error.message, url),
+            None => format!("GitHub: {}", api_error.message),
+        });
+    }
+    let end = body
+        .char_indices()
+        .map(|(i, _)| i)
+        .nth(PAYLOAD_SNIPPET_LEN)
+        .unwrap_or(body.len());
+
+    Error::Parse(format!(
+        "{}, the payload starts with: {}",
+        err,
+        &body[..end]
+    ))
+}
+
+impl Client {
+    /// Opens the database under the given directory, creating the tables on first use.
+    pub fn new(path: impl Into<PathBuf> + Send, token: Option<String>) -> Result<Self, Error> {
+        Self::with_storage(path, token, StorageKind::Rocksdb)
+    }
+
+    /// Like `new`, `StorageKind::Memory` ignores the path and starts from an empty database.
+    pub fn with_storage(
+        path: impl Into<PathBuf> + Send,
+        token: Option<String>,
+        storage: StorageKind,
+    ) -> Result<Self, Error> {
+        let (path, temp_dir) = match storage {
+            StorageKind::Rocksdb => (path.into(), None),
+            StorageKind::Memory => {
+                let path = std::env::temp_dir().join(format!(
+                    "issue-hunter-{}-{}",
+                    std::process::id(),
+                    Utc::now().timestamp_nanos_opt().unwrap_or_default()
+                ));
+                (path.clone(), Some(TempDir(path)))
+            }
+        };
+        let client = Client {
+            // Sends `Accept-Encoding` and decompresses transparently, issue pages shrink a lot
+            client: reqwest::Client::builder()
+                .gzip(true)
+                .deflate(true)
+                .build()?,
+            database: DataBaseBuilder::path(&path)
+                .build()
+                .map_err(|err| storage_error(err, &path))?,
+            token,
+            api_url: GITHUB_API_URL.to_string(),
+            limiter: Semaphore::new(DEFAULT_CONCURRENCY),
+            attempts: DEFAULT_ATTEMPTS,
+            stats: Default::default(),
+            _temp_dir: temp_dir,
+        };
+        client.create_table()?;
+
+        Ok(client)
+    }
+
+    pub fn database(&self) -> &SqlBase {
+        &self.database
+    }
+
+    fn create_table(&self) -> Result<(), Error> {
+        self.database
+            .run(
+                "CREATE TABLE IF NOT EXISTS repos (
+    owner_name VARCHAR(255) NOT NULL,
+    name VARCHAR(255) NOT NULL,
+    default_branch VARCHAR(255),
+    last_updated DATETIME,
+    archived BOOLEAN,
+    first_sync_page BIGINT,
+    token_env VARCHAR(255),
+    PRIMARY KEY (owner_name, name)
+);",
+            )?
+            .done()?;
+        self.add_column_if_missing("repos", "default_branch", "VARCHAR(255)")?;
+        self.add_column_if_missing("repos", "last_updated", "DATETIME")?;
+        self.add_column_if_missing("repos", "archived", "BOOLEAN")?;
+        self.add_column_if_missing("repos", "first_sync_page", "BIGINT")?;
+        self.add_column_if_missing("repos", "token_env", "VARCHAR(255)")?;
+        self.database
+            .run(
+                "CREATE TABLE IF NOT EXISTS users (
+    id BIGINT PRIMARY KEY,
+    login VARCHAR(255) NOT NULL
+);",
+            )?
+            .done()?;
+        self.database
+            .run(
+                "CREATE TABLE IF NOT EXISTS labels (
+    id BIGINT PRIMARY KEY,
+    name VARCHAR(255) NOT NULL,
+    description VARCHAR(255),
+    color VARCHAR(16)
+);",
+            )?
+            .done()?;
+        self.add_column_if_missing("labels", "color", "VARCHAR(16)")?;
+        self.database
+            .run(
+                "CREATE TABLE IF NOT EXISTS issues (
+    id BIGINT PRIMARY KEY,
+    number BIGINT NOT NULL,
+    title TEXT NOT NULL,
+    state VARCHAR(50) NOT NULL,
+    repo_name VARCHAR(255) NOT NULL,
+    user_id BIGINT NOT NULL,
+    created_at DATETIME NOT NULL,
+    closed_at DATETIME,
+    body TEXT,
+    raw TEXT,
+    parent_issue_url TEXT
+);",
+            )?
+            .done()?;
+        self.add_column_if_missing("issues", "closed_at", "DATETIME")?;
+        self.add_column_if_missing("issues", "body", "TEXT")?;
+        self.add_column_if_missing("issues", "raw", "TEXT")?;
+        self.add_column_if_missing("issues", "parent_issue_url", "TEXT")?;
+        self.database
+            .run(
+                "CREATE TABLE IF NOT EXISTS prs (
+    id BIGINT PRIMARY KEY,
+    number BIGINT NOT NULL,
+    title TEXT NOT NULL,
+    state VARCHAR(50) NOT NULL,
+    repo_name VARCHAR(255) NOT NULL,
+    user_id BIGINT NOT NULL,
+    created_at DATETIME NOT NULL,
+    merged BOOLEAN NOT NULL,
+    head VARCHAR(255),
+    base VARCHAR(255),
+    closed_at DATETIME,
+    body TEXT,
+    raw TEXT,
+    parent_issue_url TEXT
+);",
+            )?
+            .done()?;
+        self.add_column_if_missing("prs", "closed_at", "DATETIME")?;
+        self.add_column_if_missing("prs", "body", "TEXT")?;
+        self.add_column_if_missing("prs", "raw", "TEXT")?;
+        self.add_column_if_missing("prs", "parent_issue_url", "TEXT")?;
+        self.database
+            .run(
+                "CREATE TABLE IF NOT EXISTS repo_topics (
+    repo_name VARCHAR(255),
+    topic VARCHAR(255),
+    PRIMARY KEY (repo_name, topic)
+);",
+            )?
+            .done()?;
+        self.database
+            .run(
+                "CREATE TABLE IF NOT EXISTS checkpoints (
+    repo_name VARCHAR(255) PRIMARY KEY,
+    sort_by VARCHAR(50) NOT NULL,
+    page BIGINT NOT NULL,
+    oldest_created DATETIME
+);",
+            )?
+            .done()?;
+        self.database
+            .run(
+                "CREATE TABLE IF NOT EXISTS etags (
+    url VARCHAR(255) PRIMARY KEY,
+    etag VARCHAR(255) NOT NULL,
+    oldest_created DATETIME NOT NULL
+);",
+            )?
+            .done()?;
+        self.database
+            .run(
+                "CREATE TABLE IF NOT EXISTS issue_labels (
+    issue_id BIGINT,
+    label_id BIGINT,
+    PRIMARY KEY (issue_id, label_id)
+);",
+            )?
+            .done()?;
+        self.database
+            .run(
+                "CREATE TABLE IF NOT EXISTS discussions (
+    id BIGINT PRIMARY KEY,
+    number BIGINT NOT NULL,
+    title TEXT NOT NULL,
+    repo_name VARCHAR(255) NOT NULL,
+    author VARCHAR(255),
+    category VARCHAR(255),
+    created_at DATETIME NOT NULL
+);",
+            )?
+            .done()?;
+        self.database
+            .run(
+                "CREATE TABLE IF NOT EXISTS reactions (
+    issue_id BIGINT PRIMARY KEY,
+    plus_one INT NOT NULL,
+    minus_one INT NOT NULL,
+    laugh INT NOT NULL,
+    hooray INT NOT NULL,
+    confused INT NOT NULL,
+    heart INT NOT NULL,
+    rocket INT NOT NULL,
+    eyes INT NOT NULL
+);",
+            )?
+            .done()?;
+        self.database
+            .run(
+                "CREATE TABLE IF NOT EXISTS milestones (
+    id BIGINT PRIMARY KEY,
+    number BIGINT NOT NULL,
+    title TEXT NOT NULL,
+    state VARCHAR(255) NOT NULL,
+    repo_name VARCHAR(255) NOT NULL,
+    due_on DATETIME
+);",
+            )?
+            .done()?;
+        self.database
+            .run(
+                "CREATE TABLE IF NOT EXISTS first_responses (
+    issue_id BIGINT PRIMARY KEY,
+    user_id BIGINT
+);",
+            )?
+            .done()?;
+        self.database
+            .run(
+                "CREATE TABLE IF NOT EXISTS assignees (
+    issue_id BIGINT,
+    user_id BIGINT,
+    PRIMARY KEY (issue_id, user_id)
+);",
+            )?
+            .done()?;
+        self.database
+            .run(
+                "CREATE TABLE IF NOT EXISTS locks (
+    issue_id BIGINT PRIMARY KEY,
+    reason VARCHAR(255)
+);",
+            )?
+            .done()?;
+        self.database
+            .run(
+                "CREATE TABLE IF NOT EXISTS issue_milestones (
+    issue_id BIGINT PRIMARY KEY,
+    milestone_id BIGINT NOT NULL
+);",
+            )?
+            .done()?;
+        self.database
+            .run(
+                "CREATE TABLE IF NOT EXISTS snapshot_issues (
+    id BIGINT PRIMARY KEY,
+    repo_name VARCHAR(255) NOT NULL,
+    number BIGINT NOT NULL,
+    state VARCHAR(255) NOT NULL,
+    labels TEXT NOT NULL,
+    taken_at DATETIME NOT NULL
+);",
+            )?
+            .done()?;
+        self.database
+            .run(
+                "CREATE TABLE IF NOT EXISTS notes (
+    issue_id BIGINT PRIMARY KEY,
+    note TEXT NOT NULL
+);",
+            )?
+            .done()?;
+        self.database
+            .run(
+                "CREATE TABLE IF NOT EXISTS reopens (
+    issue_id BIGINT PRIMARY KEY,
+    reopened_count INT NOT NULL
+);",
+            )?
+            .done()?;
+
+        Ok(())
+    }
+
+    /// Upgrades tables created by older versions, `ADD COLUMN IF NOT EXISTS` would rewrite every row.
+    fn add_column_if_missing(
+        &self,
+        table: &str,
+        column: &str,
+        definition: &str,
+    ) -> Result<(), Error> {
+        for tuple in self.database.run(format!("describe {}", table))? {
+            if tuple?.values[0].utf8() == Some(column) {
+                return Ok(());
+            }
+        }
+        self.database
+            .run(format!(
+                "alter table {} add column {} {}",
+                table, column, definition
+            ))?
+            .done()?;
+
+        Ok(())
+    }
+
+    pub fn fetch_issues<'a>(
+        &'a self,
+        args: &FetchArgs,
+    ) -> Result<impl Iterator<Item = Result<Issue, Error>> + 'a, Error> {
+        let query = self.resolve_fetch_query("issues", args)?;
+        let iter = self
+            .database
+            .run(&query)
+            .map_err(|err| query_error(err, &query))?;
+        let schema = iter.schema().clone();
+        Ok(iter.map(move |result| {
+            result
+                .map(|tuple| Issue::from((&schema, tuple)))
+                .map_err(Error::from)
+        }))
+    }
+
+    pub fn fetch_prs<'a>(
+        &'a self,
+        args: &FetchArgs,
+    ) -> Result<impl Iterator<Item = Result<PullRequest, Error>> + 'a, Error> {
+        let query = self.resolve_fetch_query("prs", args)?;
+        let iter = self
+            .database
+            .run(&query)
+            .map_err(|err| query_error(err, &query))?;
+        let schema = iter.schema().clone();
+        Ok(iter.map(move |result| {
+            result
+                .map(|tuple| PullRequest::from((&schema, tuple)))
+                .map_err(Error::from)
+        }))
+    }
+
+    /// Counts the issues matching the filters of `args`, ignoring the pagination.
+    pub fn count_issues(&self, args: &FetchArgs) -> Result<u64, Error> {
+        self.count_rows("issues", args)
+    }
+
+    pub fn count_prs(&self, args: &FetchArgs) -> Result<u64, Error> {
+        self.count_rows("prs", args)
+    }
+
+    fn count_rows(&self, table: &str, args: &FetchArgs) -> Result<u64, Error> {
+        let query = format!(
+            "select count(*) from {} where {}",
+            table,
+            self.filter_clause(table, args)?
+        );
+        let count = self
+            .database
+            .run(&query)
+            .map_err(|err| query_error(err, &query))?
+            .next()
+            .transpose()?
+            .and_then(|tuple| tuple.values[0].i32())
+            .unwrap_or_default();
+
+        Ok(count as u64)
+    }
+
+    pub fn group_issues(
+        &self,
+        args: &FetchArgs,
+        group_by: GroupBy,
+    ) -> Result<Vec<(String, u64)>, Error> {
+        self.group_counts("issues", args, group_by)
+    }
+
+    pub fn group_prs(
+        &self,
+        args: &FetchArgs,
+        group_by: GroupBy,
+    ) -> Result<Vec<(String, u64)>, Error> {
+        self.group_counts("prs", args, group_by)
+    }
+
+    /// Counts the open issues per age bucket, the youngest bucket first.
+    pub fn age_histogram(
+        &self,
+        args: &AgeHistogramArgs,
+        now: DateTime<Utc>,
+    ) -> Result<Vec<(String, u64)>, Error> {
+        let mut query = "select created_at from issues where state = 'open'".to_string();
+        if let Some(repo_name) = &args.repo_name {
+            query.push_str(&format!(
+                " and repo_name like '{}'",
+                escape_sql_string(repo_name)
+            ));
+        }
+        let mut counts = [0; AGE_BUCKETS.len() + 1];
+        for tuple in self.database.run(query)? {
+            let created_at = tuple?.values[0].datetime().unwrap().and_utc();
+            let age = now - created_at;
+            let bucket = AGE_BUCKETS
+                .iter()
+                .position(|(days, _)| age < TimeDelta::days(*days))
+                .unwrap_or(AGE_BUCKETS.len());
+            counts[bucket] += 1;
+        }
+        let last = format!(">{}d", AGE_BUCKETS[AGE_BUCKETS.len() - 1].0);
+
+        Ok(AGE_BUCKETS
+            .iter()
+            .map(|(_, name)| name.to_string())
+            .chain([last])
+            .zip(counts)
+            .collect())
+    }
+
+    /// Counts the issues created per bucket, oldest first. Buckets without issues between the
+    /// first and the last one are kept with a count of 0, so the series has no gaps.
+    pub fn timeseries(&self, args: &TimeseriesArgs) -> Result<Vec<(NaiveDate, u64)>, Error> {
+        let mut query = "select created_at from issues".to_string();
+        if let Some(repo_name) = &args.repo_name {
+            query.push_str(&format!(
+                " where repo_name like '{}'",
+                escape_sql_string(repo_name)
+            ));
+        }
+        let bucket_start = |date: NaiveDate| match args.bucket {
+            Bucket::Day => date,
+            Bucket::Week => date - TimeDelta::days(date.weekday().num_days_from_monday() as i64),
+            Bucket::Month => date.with_day(1).unwrap(),
+        };
+        let mut counts = BTreeMap::<NaiveDate, u64>::new();
+        for tuple in self.database.run(query)? {
+            let created_at = tuple?.values[0].datetime().unwrap().date();
+            *counts.entry(bucket_start(created_at)).or_default() += 1;
+        }
+        let (Some(first), Some(last)) = (counts.keys().next(), counts.keys().next_back()) else {
+            return Ok(Vec::new());
+        };
+        let mut series = Vec::new();
+        let mut bucket = *first;
+        while bucket <= *last {
+            series.push((bucket, counts.get(&bucket).copied().unwrap_or_default()));
+            bucket = match args.bucket {
+                Bucket::Day => bucket + TimeDelta::days(1),
+                Bucket::Week => bucket + TimeDelta::days(7),
+                Bucket::Month => bucket + Months::new(1),
+            };
+        }
+
+        Ok(series)
+    }
+
+    /// Counts the filtered rows per group, the largest groups first.
+    fn group_counts(
+        &self,
+        table: &str,
+        args: &FetchArgs,
+        group_by: GroupBy,
+    ) -> Result<Vec<(String, u64)>, Error> {
+        let filter = self.filter_clause(table, args)?;
+        let count = |values: &[DataValue]| values[1].i32().unwrap() as u64;
+        let mut groups = Vec::new();
+
+        match group_by {
+            GroupBy::Repo | GroupBy::State => {
+                let column = if let GroupBy::Repo = group_by {
+                    "repo_name"
+                } else {
+                    "state"
+                };
+                for tuple in self.database.run(format!(
+                    "select {}, count(*) from {} where {} group by {}",
+                    column, table, filter, column
+                ))? {
+                    let values = tuple?.values;
+                    groups.push((values[0].utf8().unwrap().to_string(), count(&values)));
+                }
+            }
+            // Joining would make `id` and the filter columns ambiguous, so names are looked up after
+            GroupBy::User => {
+                for tuple in self.database.run(format!(
+                    "select user_id, count(*) from {} where {} group by user_id",
+                    table, filter
+                ))? {
+                    let values = tuple?.values;
+                    let user_id = id_value(&values[0]);
+                    groups.push((self.lookup_name("users", "login", user_id)?, count(&values)));
+                }
+            }
+            GroupBy::Label => {
+                let mut ids = Vec::new();
+                for tuple in self
+                    .database
+                    .run(format!("select id from {} where {}", table, filter))?
+                {
+                    ids.push(id_value(&tuple?.values[0]).to_string());
+                }
+                if ids.is_empty() {
+                    return Ok(groups);
+                }
+                for tuple in self.database.run(format!(
+                    "select label_id, count(*) from issue_labels where {} group by label_id",
+                    in_lists("issue_id", &ids, false)
+                ))? {
+                    let values = tuple?.values;
+                    let label_id = id_value(&values[0]);
+                    groups.push((
+                        self.lookup_name("labels", "name", label_id)?,
+                        count(&values),
+                    ));
+                }
+            }
+        }
+        groups.sort_by(|(a_name, a_count), (b_name, b_count)| {
+            b_count.cmp(a_count).then_with(|| a_name.cmp(b_name))
+        });
+
+        Ok(groups)
+    }
+
+    /// Falls back to the id when the row is missing, see `check`.
+    fn lookup_name(&self, table: &str, column: &str, id: u64) -> Result<String, Error> {
+        let name = self
+            .database
+            .run(format!(
+                "select {} from {} where id = {}",
+                column, table, id
+            ))?
+            .next()
+            .transpose()?
+            .and_then(|tuple| tuple.values[0].utf8().map(str::to_string));
+
+        Ok(name.unwrap_or_else(|| id.to_string()))
+    }
+
+    /// The query `fetch` would run, filters resolved to ids and ties broken by id.
+    pub fn explain(&self, table: &str, args: &FetchArgs) -> Result<String, Error> {
+        Ok(fetch_query(
+            table,
+            &self.filter_clause(table, args)?,
+            args,
+            true,
+        ))
+    }
+
+    fn resolve_fetch_query(&self, table: &str, args: &FetchArgs) -> Result<String, Error> {
+        let filter = self.filter_clause(table, args)?;
+        // `id` breaks ties so pages never overlap, but kite_sql panics when sorting a single row
+        // by more keys than there are rows. What is sorted is every row of the filters and the cursor
+        let tiebreaker = args.since_id.is_none()
+            && self.matches_several(table, &format!("{}{}", filter, cursor_condition(args)))?;
+
+        Ok(fetch_query(table, &filter, args, tiebreaker))
+    }
+
+    /// Whether at least two rows meet the condition, stops reading at the second one.
+    fn matches_several(&self, table: &str, condition: &str) -> Result<bool, Error> {
+        let query = format!("select id from {} where {} limit 2", table, condition);
+        let mut rows = 0;
+        for tuple in self
+            .database
+            .run(&query)
+            .map_err(|err| query_error(err, &query))?
+        {
+            tuple?;
+            rows += 1;
+        }
+
+        Ok(rows == 2)
+    }
+
+    /// The conditions of the filters given in `args` over `issues` or `prs`.
+    fn filter_clause(&self, table: &str, args: &FetchArgs) -> Result<String, Error> {
+        let mut query = filter_conditions(args, Utc::now())?;
+
+        if let Some(text) = &args.contains {
+            // Functions are not supported in `where`, so the case-insensitive match happens here
+            let text = text.to_lowercase();
+            let mut issue_ids = Vec::new();
+            let iter = self.database.run(format!("select * from {}", table))?;
+            let schema = iter.schema().clone();
+            for tuple in iter {
+                let issue = Issue::from((&schema, tuple?));
+                let body = issue.body.as_deref().unwrap_or_default();
+                if issue.title.to_lowercase().contains(&text) || body.to_lowercase().contains(&text)
+                {
+                    issue_ids.push(issue.id.to_string());
+                }
+            }
+            query.push_str(&id_condition(&issue_ids));
+        }
+        if let Some(topic) = &args.topic {
+            // GitHub keeps topics lowercase
+            let mut repo_names = Vec::new();
+            for tuple in self.database.run(format!(
+                "select repo_name from repo_topics where topic = '{}'",
+                escape_sql_string(&topic.to_lowercase())
+            ))? {
+                repo_names.push(format!(
+                    "'{}'",
+                    escape_sql_string(tuple?.values[0].utf8().unwrap_or_default())
+                ));
+            }
+            if repo_names.is_empty() {
+                query.push_str(" and 1 = 0");
+            } else {
+                query.push_str(&format!(
+                    " and {}",
+                    in_lists("repo_name", &repo_names, false)
+                ));
+            }
+        }
+        if let Some(label_name) = &args.label_name {
+            let mut label_ids = self.label_ids_by_name(label_name, args.label_exact)?;
+            if label_ids.is_empty() {
+                let mut names = Vec::new();
+                for tuple in self.database.run("select name from labels")? {
+                    names.push(tuple?.values[0].utf8().unwrap_or_default().to_string());
+                }
+                let suggestions = label_suggestions(label_name, names.iter());
+                match suggestions.as_slice() {
+                    [] => return Err(Error::NotFound(format!("Label: '{}'", label_name))),
+                    [suggestion] if args.fuzzy => {
+                        eprintln!("Label '{}' not found, using '{}'", label_name, suggestion);
+                        label_ids = self.label_ids_by_name(suggestion, args.label_exact)?;
+                    }
+                    _ => {
+                        return Err(Error::LabelSuggestion(
+                            label_name.clone(),
+                            suggestions
+                                .iter()
+                                .map(|suggestion| format!("'{}'", suggestion))
+                                .collect::<Vec<_>>()
+                                .join(" or "),
+                        ))
+                    }
+                }
+            }
+            let issue_ids = self.issues_with_any_label(&label_ids)?;
+            query.push_str(&id_condition(
+                &issue_ids.iter().map(u64::to_string).collect::<Vec<_>>(),
+            ));
+        }
+        if !args.label_id.is_empty() {
+            let mut label_ids = args.label_id.clone();
+            label_ids.sort_unstable();
+            label_ids.dedup();
+            let mut matched = HashMap::<u64, usize>::new();
+            for tuple in self.database.run(format!(
+                "select issue_id from issue_labels where {}",
+                in_lists(
+                    "label_id",
+                    &label_ids.iter().map(u64::to_string).collect::<Vec<_>>(),
+                    false
+                )
+            ))? {
+                *matched.entry(id_value(&tuple?.values[0])).or_default() += 1;
+            }
+            let issue_ids = matched
+                .into_iter()
+                .filter(|(_, count)| match args.label_match {
+                    LabelMatch::Any => true,
+                    LabelMatch::All => *count == label_ids.len(),
+                })
+                .map(|(issue_id, _)| issue_id.to_string())
+                .collect::<Vec<_>>();
+            query.push_str(&id_condition(&issue_ids));
+        }
+        if let Some(text) = &args.label_desc_contains {
+            // Older versions stored missing descriptions as the text 'null'
+            let mut label_ids = Vec::new();
+            for tuple in self.database.run(format!(
+                "select id from labels where description like '%{}%' escape '\\' and description <> 'null'",
+                escape_like(text)
+            ))? {
+                label_ids.push(id_value(&tuple?.values[0]));
+            }
+            let issue_ids = self.issues_with_any_label(&label_ids)?;
+            query.push_str(&id_condition(
+                &issue_ids.iter().map(u64::to_string).collect::<Vec<_>>(),
+            ));
+        }
+        if args.milestone_due_before.is_some() || args.milestone_due_after.is_some() {
+            // Milestones without a due date never match, nor do issues without a milestone
+            let mut condition = "due_on is not null".to_string();
+            if let Some(before) = args.milestone_due_before {
+                condition.push_str(&format!(
+                    " and due_on < '{}'",
+                    before.format("%Y-%m-%d %H:%M:%S")
+                ));
+            }
+            if let Some(after) = args.milestone_due_after {
+                condition.push_str(&format!(
+                    " and due_on > '{}'",
+                    after.format("%Y-%m-%d %H:%M:%S")
+                ));
+            }
+            let mut milestone_ids = Vec::new();
+            for tuple in self
+                .database
+                .run(format!("select id from milestones where {}", condition))?
+            {
+                milestone_ids.push(id_value(&tuple?.values[0]).to_string());
+            }
+            let mut issue_ids = Vec::new();
+            if !milestone_ids.is_empty() {
+                for tuple in self.database.run(format!(
+                    "select issue_id from issue_milestones where {}",
+                    in_lists("milestone_id", &milestone_ids, false)
+                ))? {
+                    issue_ids.push(id_value(&tuple?.values[0]).to_string());
+                }
+            }
+            query.push_str(&id_condition(&issue_ids));
+        }
+        if args.unassigned {
+            // Resolved here, the query allows a single `in` subquery which `--unlabeled` takes
+            let mut assigned = Vec::new();
+            for tuple in self.database.run("select issue_id from assignees")? {
+                assigned.push(id_value(&tuple?.values[0]).to_string());
+            }
+            assigned.sort_unstable();
+            assigned.dedup();
+            if !assigned.is_empty() {
+                query.push_str(&format!(" and {}", in_lists("id", &assigned, true)));
+            }
+        }
+
+        Ok(query)
+    }
+
+    /// Returns the number of issues and pull requests synced per repo.
+    pub async fn update_issues(&self, args: &UpdateArgs) -> Result<Vec<(String, usize)>, Error> {
+        if let Some(since) = args.closed_since {
+            return self.update_closed_issues(args, since).await;
+        }
+        let repos = self.active_repos()?;
+        if args.with_discussions
+            && self.token.is_none()
+            && repos.iter().any(|repo| repo.token_env.is_none())
+        {
+            return Err(Error::Config(
+                "--with-discussions requires GITHUB_TOKEN to be set".to_string(),
+            ));
+        }
+        let progress = if args.quiet || !std::io::stderr().is_terminal() {
+            MultiProgress::with_draw_target(ProgressDrawTarget::hidden())
+        } else {
+            MultiProgress::new()
+        };
+
+        // Repos sync concurrently, the limiter keeps the number of requests in flight in check
+        future::try_join_all(
+            repos
+                .into_iter()
+                .map(|repo| self.update_repo(args, &progress, repo)),
+        )
+        .await
+    }
+
+    async fn update_repo(
+        &self,
+        args: &UpdateArgs,
+        progress: &MultiProgress,
+        mut repo: Repo,
+    ) -> Result<(String, usize), Error> {
+        let token = self.repo_token(&repo)?;
+        if let Some(metadata) = self.fetch_repo_metadata(&repo).await {
+            // GitHub redirects renamed repositories, the metadata carries the new name
+            if !metadata.full_name.eq_ignore_ascii_case(&repo.full_name()) {
+                let Repo {
+                    owner_name, name, ..
+                } = Repo::from_str(&metadata.full_name)?;
+                let renamed = Repo {
+                    owner_name,
+                    name,
+                    ..repo.clone()
+                };
+                self.rename_repo(&repo, &renamed)?;
+                repo = renamed;
+            }
+            if metadata.default_branch.is_some() && metadata.default_branch != repo.default_branch {
+                repo.default_branch = metadata.default_branch;
+                repo.insert(&self.database)?;
+            }
+            self.store_topics(&repo, &metadata.topics)?;
+        }
+        let progress = progress.add(Self::progress_bar(args, &repo)?);
+
+        // An interrupted run, or a capped first sync, continues where it stopped
+        let first_sync = repo.last_updated.is_none();
+        let checkpoint =
+            Checkpoint::load(&self.database, &repo.full_name())?.filter(|checkpoint| {
+                !args.prune_missing && checkpoint.sort_by == args.sort_by.as_str()
+            });
+        let mut page = checkpoint
+            .as_ref()
+            .map(|checkpoint| checkpoint.page)
+            .or(repo.first_sync_page.filter(|_| first_sync))
+            .unwrap_or(1);
+        // Pruning needs every issue of the repo, so it walks all pages of all states uncached
+        let prune = args.prune_missing && page == 1;
+        let mut seen = HashSet::new();
+        let mut issue_count = 0;
+        let created_after = if let Some(datetime) = args.create_after {
+            datetime.timestamp()
+        } else {
+            let now = Utc::now();
+            let today_midnight = Utc
+                .with_ymd_and_hms(now.year(), now.month(), now.day(), 0, 0, 0)
+                .unwrap();
+
+            today_midnight.timestamp()
+        };
+        if args.with_discussions {
+            self.update_discussions(&repo, created_after).await?;
+        }
+        let mut oldest_created = checkpoint
+            .and_then(|checkpoint| checkpoint.oldest_created)
+            .map(|oldest_created| oldest_created.timestamp());
+        while prune
+            || oldest_created
+                .as_ref()
+                .map(|created| *created > created_after)
+                .unwrap_or(true)
+        {
+            let url = format!(
+                "{}/repos/{}/issues?sort={}&direction=desc{}&page={}",
+                self.api_url,
+                repo.full_name(),
+                args.sort_by.as_str(),
+                if prune { "&state=all" } else { "" },
+                page,
+            );
+
+            let cached = if prune {
+                None
+            } else {
+                ETag::load(&self.database, &url)?
+            };
+            let mut request = self.get_as(&url, token.as_deref());
+            if let Some(cached) = &cached {
+                request = request.header(IF_NONE_MATCH, &cached.etag);
+            }
+            let response = self.send(request).await?;
+
+            // The page is unchanged since the last sync, so only its age matters
+            if response.status() == StatusCode::NOT_MODIFIED {
+                if let Some(cached) = cached {
+                    let page_oldest = cached.oldest_created.timestamp();
+                    oldest_created =
+                        Some(oldest_created.map_or(page_oldest, |t| min(t, page_oldest)));
+                }
+                progress.set_message(format!("{} pages, {} issues", page, issue_count));
+                page += 1;
+                self.save_checkpoint(&repo, args, page, oldest_created)?;
+                continue;
+            }
+            let response = match check_response(response) {
+                Ok(response) => response,
+                Err(err) => {
+                    progress.abandon();
+                    return Err(err);
+                }
+            };
+            let etag = response
+                .headers()
+                .get(ETAG)
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.to_string());
+            let issues = self.issue_page(response, args.store_raw).await?;
+            if issues.is_empty() {
+                break;
+            }
+            // The cached age of a page is the oldest value of its sort field
+            let page_oldest = issues
+                .iter()
+                .filter_map(|issue| match args.sort_by {
+                    SortBy::Created => Some(issue.created_at),
+                    SortBy::Updated => issue.updated_at,
+                    SortBy::Comments => None,
+                })
+                .min();
+            for mut issue in issues {
+                issue.repo_name = repo.full_name();
+                seen.insert(issue.id);
+                // Everything is new on the first sync of a repo
+                #[cfg(feature = "notify")]
+                if repo.last_updated.is_some() {
+                    self.notify_if_new(args, &issue)?;
+                }
+                if args.with_first_response {
+                    self.update_first_response(&issue, token.as_deref()).await?;
+                }
+                match issue.pull_request.take() {
+                    Some(pull_request) => self
+                        .fetch_pull_request(issue, pull_request, token.as_deref())
+                        .await?
+                        .insert(&self.database)?,
+                    None => issue.insert(&self.database)?,
+                }
+                issue_count += 1;
+            }
+            if let Some(page_oldest) = page_oldest {
+                if let Some(etag) = etag {
+                    ETag {
+                        url,
+                        etag,
+                        oldest_created: page_oldest,
+                    }
+                    .insert(&self.database)?;
+                }
+                let page_oldest = page_oldest.timestamp();
+                oldest_created = Some(oldest_created.map_or(page_oldest, |t| min(t, page_oldest)));
+            }
+            progress.set_message(format!("{} pages, {} issues", page, issue_count));
+            page += 1;
+            self.save_checkpoint(&repo, args, page, oldest_created)?;
+            if first_sync
+                && args
+                    .first_sync_limit
+                    .is_some_and(|limit| issue_count >= limit)
+            {
+                progress.finish_with_message(format!(
+                    "first sync paused after {} issues, continues from page {}",
+                    issue_count, page
+                ));
+                repo.first_sync_page = Some(page);
+                repo.insert(&self.database)?;
+                self.clear_checkpoint(&repo)?;
+                return Ok((repo.full_name(), issue_count));
+            }
+        }
+        if prune {
+            let pruned = self.prune_missing(&repo, &seen)?;
+            progress.set_message(format!(
+                "{} pages, {} issues, {} pruned",
+                page - 1,
+                issue_count,
+                pruned
+            ));
+        }
+        progress.finish();
+
+        repo.last_updated = Some(Utc::now());
+        repo.first_sync_page = None;
+        repo.insert(&self.database)?;
+        self.clear_checkpoint(&repo)?;
+
+        Ok((repo.full_name(), issue_count))
+    }
+
+    /// Pruning runs skip it, their list of seen issues does not survive the process.
+    fn save_checkpoint(
+        &self,
+        repo: &Repo,
+        args: &UpdateArgs,
+        page: usize,
+        oldest_created: Option<i64>,
+    ) -> Result<(), Error> {
+        if args.prune_missing {
+            return Ok(());
+        }
+        Checkpoint {
+            repo_name: repo.full_name(),
+            sort_by: args.sort_by.as_str().to_string(),
+            page,
+            oldest_created: oldest_created
+                .and_then(|timestamp| DateTime::from_timestamp(timestamp, 0)),
+        }
+        .insert(&self.database)
+    }
+
+    fn clear_checkpoint(&self, repo: &Repo) -> Result<(), Error> {
+        Checkpoint {
+            repo_name: repo.full_name(),
+            ..Default::default()
+        }
+        .delete(&self.database)
+    }
+
+    /// Stores the discussions of the repo created after the timestamp, newest first.
+    async fn update_discussions(&self, repo: &Repo, created_after: i64) -> Result<(), Error> {
+        const QUERY: &str = "query($owner: String!, $name: String!, $cursor: String) {
+  repository(owner: $owner, name: $name) {
+    discussions(first: 100, after: $cursor, orderBy: {field: CREATED_AT, direction: DESC}) {
+      pageInfo { hasNextPage endCursor }
+      nodes { databaseId number title createdAt author { login } category { name } }
+    }
+  }
+}";
+        let token = self.repo_token(repo)?;
+        let mut cursor = None;
+
+        loop {
+            let body = serde_json::json!({
+                "query": QUERY,
+                "variables": { "owner": repo.owner_name, "name": repo.name, "cursor": cursor },
+            });
+            let mut request = self
+                .client
+                .post(format!("{}/graphql", self.api_url))
+                .header("User-Agent", "reqwest")
+                .json(&body);
+            if let Some(token) = &token {
+                request = request.bearer_auth(token);
+            }
+            let response = check_response(self.send(request).await?)?;
+            let response = self
+                .json_lossy::<GraphQlResponse<DiscussionsData>>(response)
+                .await?;
+            if let Some(error) = response.errors.first() {
+                return Err(Error::Parse(format!("GitHub: {}", error.message)));
+            }
+            let Some(repository) = response.data.and_then(|data| data.repository) else {
+                return Err(Error::NotFound(format!("Repo: '{}'", repo.full_name())));
+            };
+            let connection = repository.discussions;
+
+            let mut reached_known = false;
+            for node in connection.nodes {
+                if node.created_at.timestamp() <= created_after {
+                    reached_known = true;
+                    break;
+                }
+                node.into_discussion(repo.full_name())
+                    .insert(&self.database)?;
+            }
+            if reached_known || !connection.page_info.has_next_page {
+                break;
+            }
+            cursor = connection.page_info.end_cursor;
+        }
+
+        Ok(())
+    }
+
+    pub fn discussions(&self, args: &DiscussionsArgs) -> Result<Vec<Discussion>, Error> {
+        let mut query = "select * from discussions where 1 = 1".to_string();
+        if let Some(repo_name) = &args.repo_name {
+            query.push_str(&format!(
+                " and repo_name like '{}'",
+                escape_sql_string(repo_name)
+            ));
+        }
+        query.push_str(&format!(
+            " order by created_at desc limit {} offset {};",
+            args.page_size(),
+            (args.page - 1) * args.page_size()
+        ));
+        let iter = self.database.run(query)?;
+        let schema = iter.schema().clone();
+
+        iter.map(|tuple| Ok(Discussion::from((&schema, tuple?))))
+            .collect()
+    }
+
+    /// Deletes the stored issues of the repo that a complete sync did not see.
+    fn prune_missing(&self, repo: &Repo, seen: &HashSet<u64>) -> Result<usize, Error> {
+        let mut missing = Vec::new();
+        for table in ["issues", "prs"] {
+            for tuple in self.database.run(format!(
+                "select id from {} where repo_name = '{}'",
+                table,
+                escape_sql_string(&repo.full_name())
+            ))? {
+                let id = id_value(&tuple?.values[0]);
+                if !seen.contains(&id) {
+                    missing.push(id);
+                }
+            }
+        }
+        self.remove_issues(&missing)?;
+
+        Ok(missing.len())
+    }
+
+    /// Pings the desktop about an issue carrying `--notify-label` which is not stored yet.
+    #[cfg(feature = "notify")]
+    fn notify_if_new(&self, args: &UpdateArgs, issue: &Issue) -> Result<(), Error> {
+        let Some(label_name) = self.new_with_notify_label(args, issue)? else {
+            return Ok(());
+        };
+
+        let result = notify_rust::Notification::new()
+            .summary(&format!(
+                "{} #{} [{}]",
+                issue.repo_name, issue.number, label_name
+            ))
+            .body(&issue.title)
+            .show();
+        if let Err(err) = result {
+            eprintln!("Failed to notify about {}: {}", issue.number, err);
+        }
+
+        Ok(())
+    }
+
+    /// The `--notify-label` the issue carries, `None` without one or once the issue is stored.
+    #[cfg(feature = "notify")]
+    fn new_with_notify_label<'a>(
+        &self,
+        args: &'a UpdateArgs,
+        issue: &Issue,
+    ) -> Result<Option<&'a str>, Error> {
+        let Some(label_name) = &args.notify_label else {
+            return Ok(None);
+        };
+        if !issue
+            .labels
+            .iter()
+            .any(|label| label.name.eq_ignore_ascii_case(label_name))
+        {
+            return Ok(None);
+        }
+        let table = if issue.pull_request.is_some() {
+            "prs"
+        } else {
+            "issues"
+        };
+        let stored = self
+            .database
+            .run(format!("select id from {} where id = {}", table, issue.id))?
+            .next()
+            .transpose()?
+            .is_some();
+
+        Ok((!stored).then_some(label_name.as_str()))
+    }
+
+    /// Stores the author of the first comment, skipping the request while the issue has no comments
+    /// or once the author is known.
+    async fn update_first_response(&self, issue: &Issue, token: Option<&str>) -> Result<(), Error> {
+        if issue.comments > 0 {
+            let known = self
+                .database
+                .run(format!(
+                    "select user_id from first_responses where issue_id = {}",
+                    issue.id
+                ))?
+                .next()
+                .transpose()?
+                .is_some_and(|tuple| !tuple.values[0].is_null());
+            if known {
+                return Ok(());
+            }
+        }
+        let mut user_id = None;
+        if issue.comments > 0 {
+            let url = format!(
+                "{}/repos/{}/issues/{}/comments?per_page=1",
+                self.api_url, issue.repo_name, issue.number
+            );
+            let response = check_response(self.send(self.get_as(&url, token)).await?)?;
+            let comments = self.json_lossy::<Vec<Comment>>(response).await?;
+            if let Some(user) = comments.into_iter().next().and_then(|comment| comment.user) {
+                user.insert(&self.database)?;
+                user_id = Some(user.id);
+            }
+        }
+        FirstResponse {
+            issue_id: issue.id,
+            user_id,
+        }
+        .insert(&self.database)
+    }
+
+    /// `since` of the issues endpoint compares the update time, which changes on closing.
+    async fn update_closed_issues(
+        &self,
+        args: &UpdateArgs,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<(String, usize)>, Error> {
+        let repos = self.active_repos()?;
+        let mut synced = Vec::with_capacity(repos.len());
+
+        for repo in repos {
+            let token = self.repo_token(&repo)?;
+            let progress = Self::progress_bar(args, &repo)?;
+            let mut page = 1;
+            let mut issue_count = 0;
+
+            loop {
+                let url = format!(
+                    "{}/repos/{}/issues?state=closed&since={}&per_page=100&page={}",
+                    self.api_url,
+                    repo.full_name(),
+                    since.format("%Y-%m-%dT%H:%M:%SZ"),
+                    page,
+                );
+                let response =
+                    match check_response(self.send(self.get_as(&url, token.as_deref())).await?) {
+                        Ok(response) => response,
+                        Err(err) => {
+                            progress.abandon();
+                            return Err(err);
+                        }
+                    };
+                let issues = self.issue_page(response, args.store_raw).await?;
+                if issues.is_empty() {
+                    break;
+                }
+                for mut issue in issues {
+                    issue.repo_name = repo.full_name();
+                    if args.with_first_response {
+                        self.update_first_response(&issue, token.as_deref()).await?;
+                    }
+                    match issue.pull_request.take() {
+                        Some(pull_request) => self
+                            .fetch_pull_request(issue, pull_request, token.as_deref())
+                            .await?
+                            .insert(&self.database)?,
+                        None => issue.insert(&self.database)?,
+                    }
+                    issue_count += 1;
+                }
+                progress.set_message(format!("{} pages, {} closed issues", page, issue_count));
+                page += 1;
+            }
+            progress.finish();
+            synced.push((repo.full_name(), issue_count));
+        }
+
+        Ok(synced)
+    }
+
+    /// Decodes issue payloads, replacing invalid UTF-8 in titles and names rather than failing the whole page.
+    async fn json_lossy<T: DeserializeOwned>(&self, response: Response) -> Result<T, Error> {
+        let bytes = response.bytes().await?;
+        self.stats
+            .bytes
+            .fetch_add(bytes.len() as u64, Ordering::Relaxed);
+        let body = String::from_utf8_lossy(&bytes);
+
+        serde_json::from_str(&body).map_err(|err| unexpected_payload(&body, err))
+    }
+
+    async fn issue_page(&self, response: Response, store_raw: bool) -> Result<Vec<Issue>, Error> {
+        self.json_lossy::<Vec<Value>>(response)
+            .await?
+            .into_iter()
+            .map(|value| Issue::from_json(value, store_raw))
+            .collect()
+    }
+
+    /// The requests sent so far, see `update --stats`.
+    pub fn request_stats(&self) -> RequestStats {
+        RequestStats {
+            requests: self.stats.requests.load(Ordering::Relaxed),
+            not_modified: self.stats.not_modified.load(Ordering::Relaxed),
+            bytes: self.stats.bytes.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Caps the requests in flight across all repos, GitHub's secondary rate limits punish bursts.
+    /// Timeouts, connection errors and 5xx answers are retried with a jittered exponential
+    /// backoff, anything else, including 4xx, is returned as is.
+    async fn send(&self, request: RequestBuilder) -> Result<Response, reqwest::Error> {
+        let mut attempt = 1;
+
+        loop {
+            // Bodies of streams can not be cloned, those get a single attempt
+            let Some(retry) = request.try_clone().filter(|_| attempt < self.attempts) else {
+                return self.send_once(request).await;
+            };
+            match self.send_once(retry).await {
+                Ok(response) if !response.status().is_server_error() => return Ok(response),
+                Err(err) if !(err.is_timeout() || err.is_connect() || err.is_request()) => {
+                    return Err(err)
+                }
+                _ => (),
+            }
+            tokio::time::sleep(Self::backoff(attempt)).await;
+            attempt += 1;
+        }
+    }
+
+    async fn send_once(&self, request: RequestBuilder) -> Result<Response, reqwest::Error> {
+        let _permit = self
+            .limiter
+            .acquire()
+            .await
+            .expect("the limiter is never closed");
+
+        self.stats.requests.fetch_add(1, Ordering::Relaxed);
+        let response = request.send().await?;
+        if response.status() == StatusCode::NOT_MODIFIED {
+            self.stats.not_modified.fetch_add(1, Ordering::Relaxed);
+        }
+
+        Ok(response)
+    }
+
+    /// 500ms doubled for every failed attempt, plus up to half of that again so that
+    /// concurrent requests do not retry in lockstep.
+    fn backoff(attempt: u32) -> Duration {
+        let base = RETRY_BASE_DELAY * 2u32.pow(attempt - 1);
+        let jitter = Utc::now().timestamp_subsec_nanos() as u64 % (base.as_millis() as u64 / 2 + 1);
+
+        base + Duration::from_millis(jitter)
+    }
+
+    /// Sets how many requests may be in flight at once.
+    pub fn limit_concurrency(&mut self, permits: usize) {
+        self.limiter = Semaphore::new(permits);
+    }
+
+    /// Sets how often a request is tried before its transient failure is given up on.
+    pub fn retry_attempts(&mut self, attempts: u32) {
+        self.attempts = attempts;
+    }
+
+    fn get(&self, url: &str) -> RequestBuilder {
+        self.get_as(url, self.token.as_deref())
+    }
+
+    fn get_as(&self, url: &str, token: Option<&str>) -> RequestBuilder {
+        let request = self.client.get(url).header("User-Agent", "reqwest");
+
+        match token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+
+    /// The token of the repo's `token_env`, falling back to `GITHUB_TOKEN` when the repo has none.
+    /// A `token_env` that is not set is an error rather than a silent switch of accounts.
+    fn repo_token(&self, repo: &Repo) -> Result<Option<String>, Error> {
+        match &repo.token_env {
+            Some(name) => std::env::var(name).map(Some).map_err(|_| {
+                Error::Config(format!(
+                    "{} is not set, {} is synced with its token",
+                    name,
+                    repo.full_name()
+                ))
+            }),
+            None => Ok(self.token.clone()),
+        }
+    }
+
+    /// `repo_token` of a repo known only by name, the stored row carries its `token_env`.
+    /// Repos that are not tracked use `GITHUB_TOKEN`.
+    fn repo_name_token(&self, repo_name: &str) -> Result<Option<String>, Error> {
+        let repo = Repo::from_str(repo_name)?;
+        let mut iter = self.database.run(format!(
+            "select * from repos where owner_name = '{}' and name = '{}'",
+            escape_sql_string(&repo.owner_name),
+            escape_sql_string(&repo.name)
+        ))?;
+        let schema = iter.schema().clone();
+        let stored = iter
+            .next()
+            .transpose()?
+            .map(|tuple| Repo::from((&schema, tuple)));
+
+        self.repo_token(stored.as_ref().unwrap_or(&repo))
+    }
+
+    /// The issues endpoint lacks the branches, so they come from the pull request itself
+    /// at the cost of one request per pull request. A pull request that can not be fetched is
+    /// reported and stored without them, but an exhausted rate limit or a rejected token is an
+    /// error, as every following request would fail the same way.
+    async fn fetch_pull_request(
+        &self,
+        issue: Issue,
+        pull_request: PullRequestRef,
+        token: Option<&str>,
+    ) -> Result<PullRequest, Error> {
+        let response = self.send(self.get_as(&pull_request.url, token)).await?;
+        let detail = match check_response(response) {
+            Ok(response) => Some(self.json_lossy::<PullRequestDetail>(response).await?),
+            Err(err @ (Error::RateLimited(_) | Error::Status(StatusCode::UNAUTHORIZED))) => {
+                return Err(err)
+            }
+            Err(err) => {
+                eprintln!(
+                    "Failed to fetch the branches of {}#{}: {}",
+                    issue.repo_name, issue.number, err
+                );
+                None
+            }
+        };
+
+        Ok(match detail {
+            Some(detail) => PullRequest {
+                issue,
+                merged: detail.merged,
+                head: Some(detail.head.name),
+                base: Some(detail.base.name),
+            },
+            None => PullRequest {
+                issue,
+                merged: pull_request.merged_at.is_some(),
+                head: None,
+                base: None,
+            },
+        })
+    }
+
+    fn progress_bar(args: &UpdateArgs, repo: &Repo) -> Result<ProgressBar, Error> {
+        if args.quiet || !std::io::stderr().is_terminal() {
+            return Ok(ProgressBar::hidden());
+        }
+        let progress = ProgressBar::new_spinner()
+            .with_style(
+                ProgressStyle::with_template("{spinner} {prefix}: {msg}")
+                    .map_err(|err| Error::Config(err.to_string()))?,
+            )
+            .with_prefix(repo.full_name());
+        progress.enable_steady_tick(Duration::from_millis(100));
+
+        Ok(progress)
+    }
+
+    pub async fn add_repo(&self, repo: &Repo) -> Result<(), Error> {
+        let mut repo = repo.clone();
+        let metadata = self.fetch_repo_metadata(&repo).await;
+        repo.default_branch = metadata
+            .as_ref()
+            .and_then(|metadata| metadata.default_branch.clone());
+        repo.insert(&self.database)?;
+        if let Some(metadata) = metadata {
+            self.store_topics(&repo, &metadata.topics)?;
+        }
+
+        Ok(())
+    }
+
+    /// Replaces the stored topics of the repo.
+    fn store_topics(&self, repo: &Repo, topics: &[String]) -> Result<(), Error> {
+        self.database
+            .run(format!(
+                "delete from repo_topics where repo_name = '{}'",
+                escape_sql_string(&repo.full_name())
+            ))?
+            .done()?;
+        for topic in topics {
+            RepoTopic {
+                repo_name: repo.full_name(),
+                topic: topic.clone(),
+            }
+            .insert(&self.database)?;
+        }
+
+        Ok(())
+    }
+
+    /// Checking the rate limit does not count against it.
+    pub async fn rate_limit(&self) -> Result<RateLimits, Error> {
+        let response = self
+            .send(self.get(&format!("{}/rate_limit", self.api_url)))
+            .await?;
+
+        Ok(check_response(response)?.json::<RateLimits>().await?)
+    }
+
+    /// The owner of the token, `None` without a token.
+    pub async fn whoami(&self) -> Result<Option<AuthenticatedUser>, Error> {
+        if self.token.is_none() {
+            return Ok(None);
+        }
+        let response = self
+            .send(self.get(&format!("{}/user", self.api_url)))
+            .await?;
+
+        Ok(Some(check_response(response)?.json().await?))
+    }
+
+    /// Returns `None` when the repository metadata is unavailable.
+    pub async fn fetch_repo_metadata(&self, repo: &Repo) -> Option<RepoMetadata> {
+        let token = self.repo_token(repo).ok()?;
+        let request = self.get_as(
+            &format!("{}/repos/{}", self.api_url, repo.full_name()),
+            token.as_deref(),
+        );
+        let response = self.send(request).await.ok()?;
+
+        if !response.status().is_success() {
+            return None;
+        }
+        response.json::<RepoMetadata>().await.ok()
+    }
+
+    /// Moves the repo row and its issues to the new name in one transaction.
+    pub fn rename_repo(&self, from: &Repo, to: &Repo) -> Result<(), Error> {
+        let mut transaction = self.database.new_transaction()?;
+        transaction
+            .run(format!(
+                "update repos set owner_name = '{}', name = '{}' where owner_name = '{}' and name = '{}';",
+                escape_sql_string(&to.owner_name),
+                escape_sql_string(&to.name),
+                escape_sql_string(&from.owner_name),
+                escape_sql_string(&from.name)
+            ))?
+            .done()?;
+        for table in ["issues", "prs", "discussions", "checkpoints", "repo_topics"] {
+            transaction
+                .run(format!(
+                    "update {} set repo_name = '{}' where repo_name = '{}';",
+                    table,
+                    escape_sql_string(&to.full_name()),
+                    escape_sql_string(&from.full_name())
+                ))?
+                .done()?;
+        }
+        transaction.commit()?;
+
+        Ok(())
+    }
+
+    /// Corrects the name of a tracked repo, keeping its issues.
+    pub fn edit_repo(&self, args: &EditRepoArgs) -> Result<(), Error> {
+        let from = Repo::from_str(&args.from)?;
+        let to = Repo::from_str(&args.to)?;
+        let tracked = self
+            .repos()?
+            .map(|repo| repo.map(|repo| repo.full_name()))
+            .collect::<Result<HashSet<_>, _>>()?;
+        if !tracked.contains(&from.full_name()) {
+            return Err(Error::NotFound(format!("Repo: '{}'", from.full_name())));
+        }
+        if tracked.contains(&to.full_name()) {
+            return Err(Error::Parse(format!(
+                "'{}' is already tracked",
+                to.full_name()
+            )));
+        }
+
+        self.rename_repo(&from, &to)
+    }
+
+    pub async fn add_repos(
+        &self,
+        args: &AddReposArgs,
+    ) -> Result<(usize, Vec<(usize, String)>), Error> {
+        let content = fs::read_to_string(&args.file)?;
+        let mut added = 0;
+        let mut malformed = Vec::new();
+
+        for (i, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match Repo::from_str(line) {
+                Ok(repo) => {
+                    self.add_repo(&repo).await?;
+                    added += 1;
+                }
+                Err(_) => malformed.push((i + 1, line.to_string())),
+            }
+        }
+
+        Ok((added, malformed))
+    }
+
+    pub async fn import_starred(&self, args: &ImportStarredArgs) -> Result<(usize, usize), Error> {
+        let mut tracked = HashSet::new();
+        for repo in self.repos()? {
+            tracked.insert(repo?.full_name().to_lowercase());
+        }
+        let mut added = 0;
+        let mut skipped = 0;
+        let mut page = 1;
+
+        loop {
+            let url = format!(
+                "{}/users/{}/starred?per_page=100&page={}",
+                self.api_url, args.username, page
+            );
+            let response = check_response(self.send(self.get(&url)).await?)?;
+            let starred = self.json_lossy::<Vec<RepoMetadata>>(response).await?;
+            if starred.is_empty() {
+                break;
+            }
+            for metadata in starred {
+                if !tracked.insert(metadata.full_name.to_lowercase()) {
+                    skipped += 1;
+                    continue;
+                }
+                let mut repo = Repo::from_str(&metadata.full_name)?;
+                repo.default_branch = metadata.default_branch;
+                repo.insert(&self.database)?;
+                self.store_topics(&repo, &metadata.topics)?;
+                added += 1;
+            }
+            page += 1;
+        }
+
+        Ok((added, skipped))
+    }
+
+    /// Stores every label of the active repos with its current description and color,
+    /// including the labels no issue carries. Returns the number of labels stored.
+    pub async fn sync_labels(&self) -> Result<usize, Error> {
+        let mut count = 0;
+
+        for repo in self.active_repos()? {
+            let token = self.repo_token(&repo)?;
+            let mut page = 1;
+            loop {
+                let url = format!(
+                    "{}/repos/{}/labels?per_page=100&page={}",
+                    self.api_url,
+                    repo.full_name(),
+                    page
+                );
+                let response =
+                    check_response(self.send(self.get_as(&url, token.as_deref())).await?)?;
+                let labels = self.json_lossy::<Vec<Label>>(response).await?;
+                if labels.is_empty() {
+                    break;
+                }
+                for label in labels {
+                    label.insert(&self.database)?;
+                    count += 1;
+                }
+                page += 1;
+            }
+        }
+
+        Ok(count)
+    }
+
+    /// Fetches the open issues assigned to the owner of the token across all repositories.
+    pub async fn mine(&self) -> Result<Vec<Issue>, Error> {
+        if self.token.is_none() {
+            return Err(Error::Config(
+                "mine requires GITHUB_TOKEN to be set".to_string(),
+            ));
+        }
+        let mut issues = Vec::new();
+        let mut page = 1;
+
+        loop {
+            let url = format!(
+                "{}/issues?filter=assigned&state=open&per_page=100&page={}",
+                self.api_url, page
+            );
+            let response = check_response(self.send(self.get(&url)).await?)?;
+            let assigned = self.json_lossy::<Vec<CrossRepoIssue>>(response).await?;
+            if assigned.is_empty() {
+                break;
+            }
+            issues.extend(self.store_cross_repo_issues(assigned).await?);
+            page += 1;
+        }
+
+        Ok(issues)
+    }
+
+    /// Stores the results of a GitHub search query, capped at the 1000 results the search API serves.
+    /// Queries matching more issues than the search API serves are split into windows of the
+    /// creation time, bisected until each fits, unless the query has a `created:` qualifier of its own.
+    pub async fn search_github(&self, args: &SearchGithubArgs) -> Result<Vec<Issue>, Error> {
+        let mut issues = Vec::new();
+        let mut seen = HashSet::new();
+        // `None` is the query as given
+        let mut windows: Vec<Option<(DateTime<Utc>, DateTime<Utc>)>> = vec![None];
+
+        while let Some(window) = windows.pop() {
+            let query = match window {
+                Some((from, to)) => format!(
+                    "{} created:{}..{}",
+                    args.query,
+                    from.format("%Y-%m-%dT%H:%M:%SZ"),
+                    to.format("%Y-%m-%dT%H:%M:%SZ")
+                ),
+                None => args.query.clone(),
+            };
+            let mut result = self.search_page(&query, 1).await?;
+            if result.total_count > SEARCH_MAX_RESULTS && !args.query.contains("created:") {
+                let (from, to) = window.unwrap_or((SEARCH_CREATED_FROM, Utc::now()));
+                // Both ends of a range are inclusive, a single second can not be split further
+                if to - from > TimeDelta::seconds(1) {
+                    let middle = from + (to - from) / 2;
+                    windows.push(Some((middle + TimeDelta::seconds(1), to)));
+                    windows.push(Some((from, middle)));
+                    continue;
+                }
+            }
+            if result.total_count > SEARCH_MAX_RESULTS {
+                eprintln!(
+                    "Only {} of the {} results of '{}' can be fetched",
+                    SEARCH_MAX_RESULTS, result.total_count, query
+                );
+            }
+            let mut page = 1;
+            loop {
+                let is_last = result.items.len() < SEARCH_PAGE_SIZE
+                    || page * SEARCH_PAGE_SIZE >= min(result.total_count, SEARCH_MAX_RESULTS);
+                for issue in self.store_cross_repo_issues(result.items).await? {
+                    if seen.insert(issue.id) {
+                        issues.push(issue);
+                    }
+                }
+                if is_last {
+                    break;
+                }
+                page += 1;
+                result = self.search_page(&query, page).await?;
+            }
+        }
+
+        Ok(issues)
+    }
+
+    async fn search_page(&self, query: &str, page: usize) -> Result<SearchResult, Error> {
+        let mut attempt = 1;
+
+        loop {
+            let request = self
+                .get(&format!("{}/search/issues", self.api_url))
+                .query(&[("q", query)])
+                .query(&[("per_page", SEARCH_PAGE_SIZE), ("page", page)]);
+            let response = self.send(request).await?;
+            match search_retry_wait(&response) {
+                Some(wait) if attempt < SEARCH_ATTEMPTS => {
+                    tokio::time::sleep(wait).await;
+                    attempt += 1;
+                }
+                _ => {
+                    return self
+                        .json_lossy::<SearchResult>(check_response(response)?)
+                        .await
+                }
+            }
+        }
+    }
+
+    /// Items of endpoints across repositories name their repository by url.
+    async fn store_cross_repo_issues(
+        &self,
+        items: Vec<CrossRepoIssue>,
+    ) -> Result<Vec<Issue>, Error> {
+        let mut issues = Vec::with_capacity(items.len());
+
+        for CrossRepoIssue {
+            mut issue,
+            repository_url,
+        } in items
+        {
+            issue.repo_name = repository_url
+                .strip_prefix(&format!("{}/repos/", self.api_url))
+                .ok_or_else(|| {
+                    Error::Parse(format!("unexpected repository url: {}", repository_url))
+                })?
+                .to_string();
+            match issue.pull_request.take() {
+                Some(pull_request) => {
+                    let token = self.repo_name_token(&issue.repo_name)?;
+                    let pr = self
+                        .fetch_pull_request(issue, pull_request, token.as_deref())
+                        .await?;
+                    pr.insert(&self.database)?;
+                    issues.push(pr.issue);
+                }
+                None => {
+                    issue.insert(&self.database)?;
+                    issues.push(issue);
+                }
+            }
+        }
+
+        Ok(issues)
+    }
+
+    /// Derives the columns of the issues synced with `--store-raw` from their json again,
+    /// returns how many issues were reprocessed.
+    pub fn reprocess(&self) -> Result<usize, Error> {
+        let mut count = 0;
+
+        let iter = self
+            .database
+            .run("select * from issues where raw is not null")?;
+        let schema = iter.schema().clone();
+        let stored = iter
+            .map(|tuple| Ok(Issue::from((&schema, tuple?))))
+            .collect::<Result<Vec<_>, Error>>()?;
+        for stored in stored {
+            self.reprocessed(&stored)?.insert(&self.database)?;
+            count += 1;
+        }
+
+        let iter = self
+            .database
+            .run("select * from prs where raw is not null")?;
+        let schema = iter.schema().clone();
+        let stored = iter
+            .map(|tuple| Ok(PullRequest::from((&schema, tuple?))))
+            .collect::<Result<Vec<_>, Error>>()?;
+        for mut pr in stored {
+            pr.issue = self.reprocessed(&pr.issue)?;
+            pr.insert(&self.database)?;
+            count += 1;
+        }
+
+        Ok(count)
+    }
+
+    /// Stores the issues of a local json file like `update` does, without touching the network.
+    pub fn import_json(&self, args: &ImportJsonArgs) -> Result<usize, Error> {
+        let repo = Repo::from_str(&args.repo_name)?;
+        let content = fs::read_to_string(&args.path)?;
+        let mut count = 0;
+
+        for value in serde_json::Deserializer::from_str(&content).into_iter::<Value>() {
+            let value =
+                value.map_err(|err| Error::Parse(format!("{}: {}", args.path.display(), err)))?;
+            let values = match value {
+                Value::Array(values) => values,
+                value => vec![value],
+            };
+            for value in values {
+                let mut issue = Issue::from_json(value, args.store_raw)?;
+                issue.repo_name = repo.full_name();
+                match issue.pull_request.take() {
+                    // The branches need the pull request endpoint, so they stay unknown
+                    Some(pull_request) => PullRequest {
+                        merged: pull_request.merged_at.is_some(),
+                        issue,
+                        head: None,
+                        base: None,
+                    }
+                    .insert(&self.database)?,
+                    None => issue.insert(&self.database)?,
+                }
+                count += 1;
+            }
+        }
+
+        Ok(count)
+    }
+
+    /// The issue as deserialized from its stored json, the repo is not part of the json.
+    fn reprocessed(&self, stored: &Issue) -> Result<Issue, Error> {
+        let raw = stored.raw.as_deref().unwrap_or_default();
+        let value = serde_json::from_str::<Value>(raw)
+            .map_err(|err| Error::Parse(format!("raw json of issue {}: {}", stored.id, err)))?;
+        let mut issue = Issue::from_json(value, true)?;
+        issue.repo_name.clone_from(&stored.repo_name);
+
+        Ok(issue)
+    }
+
+    /// Finds the rows referencing users, labels or issues which are not stored.
+    pub fn check(&self) -> Result<Vec<Problem>, Error> {
+        let ids = |query: &str| -> Result<HashSet<u64>, Error> {
+            let mut ids = HashSet::new();
+            for tuple in self.database.run(query)? {
+                ids.insert(id_value(&tuple?.values[0]));
+            }
+            Ok(ids)
+        };
+        let user_ids = ids("select id from users")?;
+        let label_ids = ids("select id from labels")?;
+        let mut issue_ids = ids("select id from issues")?;
+        issue_ids.extend(ids("select id from prs")?);
+        let mut problems = Vec::new();
+
+        for table in ["issues", "prs"] {
+            for tuple in self
+                .database
+                .run(format!("select id, user_id from {}", table))?
+            {
+                let values = tuple?.values;
+                let user_id = id_value(&values[1]);
+                if !user_ids.contains(&user_id) {
+                    problems.push(Problem::MissingUser {
+                        table,
+                        issue_id: id_value(&values[0]),
+                        user_id,
+                    });
+                }
+            }
+        }
+        for tuple in self
+            .database
+            .run("select issue_id, label_id from issue_labels")?
+        {
+            let values = tuple?.values;
+            let link = IssueLabelLink {
+                issue_id: id_value(&values[0]),
+                label_id: id_value(&values[1]),
+            };
+            if !issue_ids.contains(&link.issue_id) {
+                problems.push(Problem::MissingIssue(link));
+            } else if !label_ids.contains(&link.label_id) {
+                problems.push(Problem::MissingLabel(link));
+            }
+        }
+
+        Ok(problems)
+    }
+
+    /// The label names of every stored issue, sorted.
+    fn label_names_by_issue(&self) -> Result<HashMap<u64, Vec<String>>, Error> {
+        let mut names = HashMap::new();
+        for tuple in self.database.run("select id, name from labels")? {
+            let values = tuple?.values;
+            names.insert(
+                id_value(&values[0]),
+                values[1].utf8().unwrap_or_default().to_string(),
+            );
+        }
+        let mut labels = HashMap::<u64, Vec<String>>::new();
+        for tuple in self
+            .database
+            .run("select issue_id, label_id from issue_labels")?
+        {
+            let values = tuple?.values;
+            if let Some(name) = names.get(&id_value(&values[1])) {
+                labels
+                    .entry(id_value(&values[0]))
+                    .or_default()
+                    .push(name.clone());
+            }
+        }
+        labels.values_mut().for_each(|names| names.sort());
+
+        Ok(labels)
+    }
+
+    /// Maps every stored user id to `user-<n>`, numbered by id so the same user keeps its name
+    /// across exports as long as no user with a lower id is added.
+    pub fn user_pseudonyms(&self) -> Result<HashMap<u64, String>, Error> {
+        let mut pseudonyms = HashMap::new();
+        for (n, tuple) in self
+            .database
+            .run("select id from users order by id asc")?
+            .enumerate()
+        {
+            pseudonyms.insert(id_value(&tuple?.values[0]), format!("user-{}", n + 1));
+        }
+
+        Ok(pseudonyms)
+    }
+
+    /// Replaces the snapshot with the state and labels of every stored issue, returns their number.
+    pub fn take_snapshot(&self) -> Result<usize, Error> {
+        let mut labels = self.label_names_by_issue()?;
+        let iter = self.database.run("select * from issues")?;
+        let schema = iter.schema().clone();
+        let issues = iter
+            .map(|tuple| Ok(Issue::from((&schema, tuple?))))
+            .collect::<Result<Vec<_>, Error>>()?;
+        let taken_at = Utc::now().format("%Y-%m-%d %H:%M:%S");
+
+        let mut transaction = self.database.new_transaction()?;
+        transaction
+            .run("delete from snapshot_issues where 1 = 1")?
+            .done()?;
+        for issue in &issues {
+            let names = labels.remove(&issue.id).unwrap_or_default();
+            transaction
+                .run(format!(
+                    "insert into snapshot_issues values({}, '{}', {}, '{}', '{}', '{}');",
+                    issue.id,
+                    escape_sql_string(&issue.repo_name),
+                    issue.number,
+                    escape_sql_string(&issue.state),
+                    escape_sql_string(&serde_json::Value::from(names).to_string()),
+                    taken_at
+                ))?
+                .done()?;
+        }
+        transaction.commit()?;
+
+        Ok(issues.len())
+    }
+
+    /// Compares the stored issues with the snapshot, returns when it was taken and the changes
+    /// ordered by kind. Issues gone since the snapshot are not reported, syncs never delete them.
+    pub fn diff_snapshot(
+        &self,
+        repo_name: Option<&str>,
+    ) -> Result<(Option<DateTime<Utc>>, Vec<IssueChange>), Error> {
+        let mut taken_at = None;
+        let mut snapshot = HashMap::new();
+        for tuple in self.database.run("select * from snapshot_issues")? {
+            let values = tuple?.values;
+            let labels = serde_json::from_str::<Vec<String>>(values[4].utf8().unwrap_or("[]"))
+                .map_err(|err| Error::Parse(format!("snapshot labels: {}", err)))?;
+            taken_at = values[5].datetime().map(|datetime| datetime.and_utc());
+            snapshot.insert(
+                id_value(&values[0]),
+                (values[3].utf8().unwrap_or_default().to_string(), labels),
+            );
+        }
+        let mut labels = self.label_names_by_issue()?;
+
+        let mut query = "select * from issues".to_string();
+        if let Some(repo_name) = repo_name {
+            query.push_str(&format!(
+                " where repo_name = '{}'",
+                escape_sql_string(repo_name)
+            ));
+        }
+        let iter = self.database.run(query)?;
+        let schema = iter.schema().clone();
+        let mut changes = Vec::new();
+        for tuple in iter {
+            let issue = Issue::from((&schema, tuple?));
+            let names = labels.remove(&issue.id).unwrap_or_default();
+            let mut change = |change, labels| {
+                changes.push(IssueChange {
+                    change,
+                    repo_name: issue.repo_name.clone(),
+                    number: issue.number,
+                    title: issue.title.clone(),
+                    labels,
+                })
+            };
+            let Some((state, snapshot_names)) = snapshot.remove(&issue.id) else {
+                change(Change::Added, None);
+                continue;
+            };
+            match (state.as_str(), issue.state.as_str()) {
+                ("open", "closed") => change(Change::Closed, None),
+                ("closed", "open") => change(Change::Reopened, None),
+                _ => (),
+            }
+            if snapshot_names != names {
+                change(Change::Relabeled, Some((snapshot_names, names)));
+            }
+        }
+        changes.sort_by(|a, b| {
+            (a.change, &a.repo_name, a.number).cmp(&(b.change, &b.repo_name, b.number))
+        });
+
+        Ok((taken_at, changes))
+    }
+
+    /// Repairs the problems found by `check`, returns how many of them were fixed.
+    pub async fn fix(&self, problems: &[Problem]) -> Result<usize, Error> {
+        let mut fetched = HashSet::new();
+        let mut fixed = 0;
+
+        for problem in problems {
+            match problem {
+                Problem::MissingUser {
+                    table,
+                    issue_id,
+                    user_id,
+                } => {
+                    if fetched.contains(user_id) {
+                        fixed += 1;
+                        continue;
+                    }
+                    let mut iter = self
+                        .database
+                        .run(format!("select * from {} where id = {}", table, issue_id))?;
+                    let schema = iter.schema().clone();
+                    let repo_name = iter
+                        .next()
+                        .transpose()?
+                        .map(|tuple| Issue::from((&schema, tuple)).repo_name);
+                    drop(iter);
+                    let token = match repo_name {
+                        Some(repo_name) => self.repo_name_token(&repo_name)?,
+                        None => self.token.clone(),
+                    };
+                    let url = format!("{}/user/{}", self.api_url, user_id);
+                    // Unavailable users stay unfixed instead of aborting the remaining fixes
+                    let request = self.get_as(&url, token.as_deref());
+                    let user = match self.send(request).await.map(check_response) {
+                        Ok(Ok(response)) => response.json::<User>().await.ok(),
+                        _ => None,
+                    };
+                    match user {
+                        Some(user) => {
+                            user.insert(&self.database)?;
+                            fetched.insert(*user_id);
+                        }
+                        None => continue,
+                    }
+                }
+                Problem::MissingLabel(link) | Problem::MissingIssue(link) => {
+                    link.delete(&self.database)?;
+                }
+            }
+            fixed += 1;
+        }
+
+        Ok(fixed)
+    }
+
+    /// Fetches a single issue again and stores it, `false` when GitHub no longer has it.
+    pub async fn refresh(&self, args: &RefreshArgs) -> Result<bool, Error> {
+        let repo = Repo::from_str(&args.repo_name)?;
+        let token = self.repo_name_token(&args.repo_name)?;
+        let url = format!(
+            "{}/repos/{}/issues/{}",
+            self.api_url,
+            repo.full_name(),
+            args.number
+        );
+        let response = self.send(self.get_as(&url, token.as_deref())).await?;
+        // Deleted issues answer with 410, transferred ones with 404
+        if matches!(response.status(), StatusCode::NOT_FOUND | StatusCode::GONE) {
+            return Ok(false);
+        }
+        let value = self.json_lossy::<Value>(check_response(response)?).await?;
+        let mut issue = Issue::from_json(value, args.store_raw)?;
+        issue.repo_name = repo.full_name();
+        match issue.pull_request.take() {
+            Some(pull_request) => self
+                .fetch_pull_request(issue, pull_request, token.as_deref())
+                .await?
+                .insert(&self.database)?,
+            None => issue.insert(&self.database)?,
+        }
+
+        Ok(true)
+    }
+
+    /// Pages through the issues updated since the last `update` of each repo and overwrites only
+    /// the state and closing time of those already stored, without labels, reactions or pull request details.
+    /// Returns the number of changed states per repo, repos never synced are skipped.
+    pub async fn refresh_states(
+        &self,
+        args: &RefreshStatesArgs,
+    ) -> Result<Vec<(String, usize)>, Error> {
+        let repos = self.active_repos()?;
+        let mut refreshed = Vec::with_capacity(repos.len());
+
+        for repo in repos {
+            let Some(since) = args.since.or(repo.last_updated) else {
+                continue;
+            };
+            let token = self.repo_token(&repo)?;
+            let mut page = 1;
+            let mut changed = 0;
+
+            loop {
+                let url = format!(
+                    "{}/repos/{}/issues?state=all&sort=updated&direction=desc&since={}&per_page=100&page={}",
+                    self.api_url,
+                    repo.full_name(),
+                    since.format("%Y-%m-%dT%H:%M:%SZ"),
+                    page,
+                );
+                let response =
+                    check_response(self.send(self.get_as(&url, token.as_deref())).await?)?;
+                let issues = self.issue_page(response, false).await?;
+                if issues.is_empty() {
+                    break;
+                }
+                for issue in issues {
+                    let table = if issue.pull_request.is_some() {
+                        "prs"
+                    } else {
+                        "issues"
+                    };
+                    if self.update_state(table, &issue)? {
+                        changed += 1;
+                    }
+                }
+                page += 1;
+            }
+            refreshed.push((repo.full_name(), changed));
+        }
+
+        Ok(refreshed)
+    }
+
+    /// `false` when the issue is not stored or its state is unchanged.
+    fn update_state(&self, table: &str, issue: &Issue) -> Result<bool, Error> {
+        let stored_state = self
+            .database
+            .run(format!(
+                "select state from {} where id = {}",
+                table, issue.id
+            ))?
+            .next()
+            .transpose()?
+            .and_then(|tuple| tuple.values[0].utf8().map(str::to_string));
+        if stored_state.is_none() || stored_state.as_deref() == Some(issue.state.as_str()) {
+            return Ok(false);
+        }
+        issue.record_reopen(table, &self.database)?;
+        self.database
+            .run(format!(
+                "update {} set state = '{}', closed_at = {} where id = {}",
+                table,
+                escape_sql_string(&issue.state),
+                issue
+                    .closed_at
+                    .map(|closed_at| format!("'{}'", closed_at.format("%Y-%m-%d %H:%M:%S")))
+                    .unwrap_or("null".to_string()),
+                issue.id
+            ))?
+            .done()?;
+
+        Ok(true)
+    }
+
+    /// The next `limit` issues with an id above `after_id`, lowest id first.
+    pub fn issues_after_id(&self, after_id: u64, limit: usize) -> Result<Vec<Issue>, Error> {
+        let iter = self.database.run(format!(
+            "select * from issues where id > {} order by id asc limit {}",
+            after_id, limit
+        ))?;
+        let schema = iter.schema().clone();
+
+        iter.map(|tuple| Ok(Issue::from((&schema, tuple?))))
+            .collect()
+    }
+
+    /// All stored issues of the repo, newest first with ties broken by id.
+    pub fn repo_issues(&self, repo_name: &str) -> Result<Vec<Issue>, Error> {
+        let iter = self.database.run(format!(
+            "select * from issues where repo_name = '{}'",
+            escape_sql_string(repo_name)
+        ))?;
+        let schema = iter.schema().clone();
+        let mut issues = iter
+            .map(|tuple| Ok(Issue::from((&schema, tuple?))))
+            .collect::<Result<Vec<_>, Error>>()?;
+        // Sorted here, kite_sql can not sort a single row by two keys
+        issues.sort_by(|a, b| (b.created_at, b.id).cmp(&(a.created_at, a.id)));
+
+        Ok(issues)
+    }
+
+    /// Ids of the stored issues and pull requests carrying the label.
+    pub fn issues_with_label(&self, label_name: &str) -> Result<Vec<u64>, Error> {
+        let label_ids = self.label_ids_by_name(label_name, true)?;
+
+        self.issues_with_any_label(&label_ids)
+    }
+
+    /// Ids of the labels with the name in every repo, each repo has label ids of its own.
+    /// Sorted and without duplicates, empty when no label matches.
+    fn label_ids_by_name(&self, label_name: &str, exact: bool) -> Result<Vec<u64>, Error> {
+        // Functions are not supported in `where`, so case-insensitive matching happens here
+        let mut label_ids = Vec::new();
+        for tuple in self.database.run("select id, name from labels")? {
+            let values = tuple?.values;
+            let name = values[1].utf8().unwrap_or_default();
+            let matches = if exact {
+                name == label_name
+            } else {
+                name.to_lowercase() == label_name.to_lowercase()
+            };
+            if matches {
+                label_ids.push(id_value(&values[0]));
+            }
+        }
+        label_ids.sort_unstable();
+        label_ids.dedup();
+
+        Ok(label_ids)
+    }
+
+    /// Ids of the stored issues and pull requests carrying any of the labels,
+    /// sorted and without duplicates.
+    fn issues_with_any_label(&self, label_ids: &[u64]) -> Result<Vec<u64>, Error> {
+        if label_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut issue_ids = Vec::new();
+        for tuple in self.database.run(format!(
+            "select issue_id from issue_labels where {}",
+            in_lists(
+                "label_id",
+                &label_ids.iter().map(u64::to_string).collect::<Vec<_>>(),
+                false
+            )
+        ))? {
+            issue_ids.push(id_value(&tuple?.values[0]));
+        }
+        issue_ids.sort_unstable();
+        issue_ids.dedup();
+
+        Ok(issue_ids)
+    }
+
+    /// Deletes the issues and pull requests with their label links and reactions in one transaction.
+    pub fn remove_issues(&self, ids: &[u64]) -> Result<(), Error> {
+        let ids = ids.iter().map(u64::to_string).collect::<Vec<_>>();
+        let mut transaction = self.database.new_transaction()?;
+        for table in ["issues", "prs"] {
+            transaction
+                .run(format!(
+                    "delete from {} where 1 = 1{}",
+                    table,
+                    id_condition(&ids)
+                ))?
+                .done()?;
+        }
+        for table in [
+            "issue_labels",
+            "reactions",
+            "reopens",
+            "notes",
+            "issue_milestones",
+            "locks",
+            "assignees",
+            "first_responses",
+        ] {
+            // The links are keyed by `issue_id`, which `id_condition` does not cover
+            if !ids.is_empty() {
+                transaction
+                    .run(format!(
+                        "delete from {} where {}",
+                        table,
+                        in_lists("issue_id", &ids, false)
+                    ))?
+                    .done()?;
+            }
+        }
+        transaction.commit()?;
+
+        Ok(())
+    }
+
+    /// Appends to, replaces or clears the note of an issue or pull request, returns the note left.
+    pub fn note(&self, args: &NoteArgs) -> Result<Option<String>, Error> {
+        let mut issue_id = None;
+        for table in ["issues", "prs"] {
+            issue_id = self
+                .database
+                .run(format!(
+                    "select id from {} where repo_name = '{}' and number = {}",
+                    table,
+                    escape_sql_string(&args.repo_name),
+                    args.number
+                ))?
+                .next()
+                .transpose()?
+                .map(|tuple| id_value(&tuple.values[0]));
+            if issue_id.is_some() {
+                break;
+            }
+        }
+        let issue_id = issue_id.ok_or_else(|| {
+            Error::NotFound(format!("Issue: '{}#{}'", args.repo_name, args.number))
+        })?;
+
+        let stored = self
+            .database
+            .run(format!(
+                "select note from notes where issue_id = {}",
+                issue_id
+            ))?
+            .next()
+            .transpose()?
+            .and_then(|tuple| tuple.values[0].utf8().map(str::to_string));
+        let note = match (&args.text, stored) {
+            (Some(text), Some(stored)) if !args.replace => Some(format!("{}\n{}", stored, text)),
+            (text, _) => text.clone(),
+        };
+        match &note {
+            Some(note) => self.database.run(format!(
+                "insert overwrite notes values({}, '{}');",
+                issue_id,
+                escape_sql_string(note)
+            ))?,
+            None => self
+                .database
+                .run(format!("delete from notes where issue_id = {};", issue_id))?,
+        }
+        .done()?;
+
+        Ok(note)
+    }
+
+    /// Deletes the stored issue or pull request of the repo, `false` when none is stored.
+    pub fn remove_issue(&self, repo_name: &str, number: u64) -> Result<bool, Error> {
+        let condition = format!(
+            "repo_name = '{}' and number = {}",
+            escape_sql_string(repo_name),
+            number
+        );
+        let mut removed = false;
+
+        let iter = self
+            .database
+            .run(format!("select * from issues where {}", condition))?;
+        let schema = iter.schema().clone();
+        let issues = iter
+            .map(|tuple| Ok(Issue::from((&schema, tuple?))))
+            .collect::<Result<Vec<_>, Error>>()?;
+        for issue in issues {
+            issue.delete(&self.database)?;
+            removed = true;
+        }
+
+        let iter = self
+            .database
+            .run(format!("select * from prs where {}", condition))?;
+        let schema = iter.schema().clone();
+        let prs = iter
+            .map(|tuple| Ok(PullRequest::from((&schema, tuple?))))
+            .collect::<Result<Vec<_>, Error>>()?;
+        for pr in prs {
+            pr.delete(&self.database)?;
+            removed = true;
+        }
+
+        Ok(removed)
+    }
+
+    /// The stored issue or pull request with the number, `None` when neither is stored.
+    pub fn issue(&self, repo_name: &str, number: u64) -> Result<Option<Issue>, Error> {
+        let condition = format!(
+            "repo_name = '{}' and number = {}",
+            escape_sql_string(repo_name),
+            number
+        );
+
+        let mut iter = self
+            .database
+            .run(format!("select * from issues where {}", condition))?;
+        let schema = iter.schema().clone();
+        if let Some(tuple) = iter.next().transpose()? {
+            return Ok(Some(Issue::from((&schema, tuple))));
+        }
+        drop(iter);
+        let mut iter = self
+            .database
+            .run(format!("select * from prs where {}", condition))?;
+        let schema = iter.schema().clone();
+
+        Ok(iter
+            .next()
+            .transpose()?
+            .map(|tuple| PullRequest::from((&schema, tuple)).issue))
+    }
+
+    /// The number of stored issues and pull requests of the repo.
+    pub fn repo_counts(&self, repo: &Repo) -> Result<(u64, u64), Error> {
+        let count = |table: &str| -> Result<u64, Error> {
+            Ok(self
+                .database
+                .run(format!(
+                    "select count(*) from {} where repo_name = '{}'",
+                    table,
+                    escape_sql_string(&repo.full_name())
+                ))?
+                .next()
+                .transpose()?
+                .and_then(|tuple| tuple.values[0].i32())
+                .unwrap_or_default() as u64)
+        };
+
+        Ok((count("issues")?, count("prs")?))
+    }
+
+    pub fn remove_repo(&self, repo: &Repo) -> Result<(), Error> {
+        repo.delete(&self.database)?;
+
+        Ok(())
+    }
+
+    /// Archiving keeps the issues but pauses the sync, `false` when the repo is not tracked.
+    pub fn set_archived(&self, repo: &Repo, archived: bool) -> Result<bool, Error> {
+        let condition = format!(
+            "owner_name = '{}' and name = '{}'",
+            escape_sql_string(&repo.owner_name),
+            escape_sql_string(&repo.name)
+        );
+        let tracked = self
+            .database
+            .run(format!("select name from repos where {}", condition))?
+            .next()
+            .transpose()?
+            .is_some();
+        if tracked {
+            self.database
+                .run(format!(
+                    "update repos set archived = {} where {}",
+                    archived, condition
+                ))?
+                .done()?;
+        }
+
+        Ok(tracked)
+    }
+
+    /// Sets the environment variable the repo's token is read from, `None` goes back to `GITHUB_TOKEN`.
+    /// `false` when the repo is not tracked.
+    pub fn set_token_env(&self, repo: &Repo, token_env: Option<&str>) -> Result<bool, Error> {
+        let condition = format!(
+            "owner_name = '{}' and name = '{}'",
+            escape_sql_string(&repo.owner_name),
+            escape_sql_string(&repo.name)
+        );
+        let tracked = self
+            .database
+            .run(format!("select name from repos where {}", condition))?
+            .next()
+            .transpose()?
+            .is_some();
+        if tracked {
+            self.database
+                .run(format!(
+                    "update repos set token_env = {} where {}",
+                    token_env
+                        .map(|name| format!("'{}'", escape_sql_string(name)))
+                        .unwrap_or("null".to_string()),
+                    condition
+                ))?
+                .done()?;
+        }
+
+        Ok(tracked)
+    }
+
+    /// The repos `update` syncs, rows from before archiving existed have no flag.
+    fn active_repos(&self) -> Result<Vec<Repo>, Error> {
+        let iter = self
+            .database
+            .run("select * from repos where archived is null or archived = false;")?;
+        let schema = iter.schema().clone();
+
+        iter.map(|result| Ok(Repo::from((&schema, result?))))
+            .collect()
+    }
+
+    pub fn repos(&self) -> Result<impl Iterator<Item = Result<Repo, Error>> + use<'_>, Error> {
+        let iter = self.database.run("select * from repos;")?;
+        let schema = iter.schema().clone();
+        Ok(iter.map(move |result| {
+            result
+                .map(|tuple| Repo::from((&schema, tuple)))
+                .map_err(Error::from)
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::User;
+    use clap::Parser;
+    use std::sync::Arc;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    type MockResponse = (u16, Vec<(&'static str, String)>, String);
+
+    /// Answers every request on a local port with the status, headers and body `respond` returns
+    /// for its path and query, one connection per request.
+    async fn mock_server(respond: impl Fn(&str) -> MockResponse + Send + 'static) -> String {
+        mock_server_with_headers(move |path, _| respond(path)).await
+    }
+
+    /// `mock_server` whose `respond` also gets the request line and headers.
+    async fn mock_server_with_headers(
+        respond: impl Fn(&str, &str) -> MockResponse + Send + 'static,
+    ) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut request = Vec::new();
+                let mut buf = [0; 1024];
+                while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+                    let len = stream.read(&mut buf).await.unwrap();
+                    if len == 0 {
+                        break;
+                    }
+                    request.extend_from_slice(&buf[..len]);
+                }
+                let request = String::from_utf8_lossy(&request);
+                let path = request.split_whitespace().nth(1).unwrap_or_default();
+                let (status, headers, body) = respond(path, &request);
+                let mut response = format!(
+                    "HTTP/1.1 {} Mock\r\nContent-Length: {}\r\nConnection: close\r\n",
+                    status,
+                    body.len()
+                );
+                for (name, value) in headers {
+                    response.push_str(&format!("{}: {}\r\n", name, value));
+                }
+                response.push_str("\r\n");
+                response.push_str(&body);
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+
+        url
+    }
+
+    fn memory_client() -> Client {
+        Client::with_storage("", None, StorageKind::Memory).unwrap()
+    }
+
+    fn issue(id: u64, repo_name: &str, created_at: &str) -> Issue {
+        Issue {
+            id,
+            number: id,
+            title: format!("issue {}", id),
+            state: "open".to_string(),
+            repo_name: repo_name.to_string(),
+            user: User {
+                id: 1,
+                login: "octocat".to_string(),
+            },
+            created_at: created_at.parse().unwrap(),
+            ..Default::default()
+        }
+    }
+
+    fn fetch_args(args: &[&str]) -> FetchArgs {
+        FetchArgs::parse_from(["fetch"].iter().chain(args))
+    }
+
+    fn fetched_ids(client: &Client, args: &[&str]) -> Vec<u64> {
+        client
+            .fetch_issues(&fetch_args(args))
+            .unwrap()
+            .map(|issue| issue.unwrap().id)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn update_follows_a_renamed_repo() {
+        let mut client = memory_client();
+        client.api_url = mock_server(|path| match path {
+            "/repos/old/name" => (
+                301,
+                vec![("Location", "/repos/new/name".to_string())],
+                String::new(),
+            ),
+            "/repos/new/name" => (200, Vec::new(), r#"{"full_name":"new/name"}"#.to_string()),
+            _ => (200, Vec::new(), "[]".to_string()),
+        })
+        .await;
+        Repo::from_str("old/name")
+            .unwrap()
+            .insert(&client.database)
+            .unwrap();
+        issue(1, "old/name", "2024-01-01T00:00:00Z")
+            .insert(&client.database)
+            .unwrap();
+
+        client
+            .update_issues(&UpdateArgs::parse_from(["update", "--quiet"]))
+            .await
+            .unwrap();
+
+        let repos = client
+            .repos()
+            .unwrap()
+            .map(|repo| repo.unwrap().full_name())
+            .collect::<Vec<_>>();
+        assert_eq!(repos, ["new/name"]);
+        assert_eq!(fetched_ids(&client, &["--repo-name", "new/name"]), [1]);
+        assert!(fetched_ids(&client, &["--repo-name", "old/name"]).is_empty());
+    }
+
+    #[test]
+    fn fetch_query_pages_by_offset() {
+        let args = fetch_args(&["--page", "3", "--page-num", "20"]);
+
+        assert_eq!(
+            fetch_query("issues", "1 = 1", &args, true),
+            "select * from issues where 1 = 1 order by created_at desc, id desc limit 20 offset 40;"
+        );
+        assert_eq!(
+            fetch_query("issues", "1 = 1", &args, false),
+            "select * from issues where 1 = 1 order by created_at desc limit 20 offset 40;"
+        );
+    }
+
+    #[test]
+    fn fetch_query_joins_the_sort_table() {
+        let args = fetch_args(&["--sort", "plus-one-desc"]);
+
+        assert_eq!(
+            fetch_query("prs", "1 = 1 and state in ('open')", &args, true),
+            "select prs.* from prs left join reactions on id = issue_id where 1 = 1 and state in ('open') \
+             order by plus_one desc, id desc limit 10 offset 0;"
+        );
+    }
+
+    #[test]
+    fn fetch_query_orders_by_id_after_since_id() {
+        let args = fetch_args(&["--since-id", "7"]);
+
+        assert_eq!(
+            fetch_query("issues", "1 = 1", &args, true),
+            "select * from issues where 1 = 1 and id > 7 order by id asc limit 10;"
+        );
+    }
+
+    #[test]
+    fn filter_conditions_escape_owner_and_excluded_repos() {
+        let args = fetch_args(&[
+            "--owner",
+            "my_org",
+            "--exclude-repo",
+            "my_org/it's",
+            "--state",
+            "open,closed",
+            "--unlabeled",
+        ]);
+
+        assert_eq!(
+            filter_conditions(&args, Utc::now()).unwrap(),
+            "1 = 1 and repo_name like 'my\\_org/%' escape '\\' \
+             and repo_name not like 'my\\_org/it''s' escape '\\' \
+             and state in ('open', 'closed') and id not in (select issue_id from issue_labels)"
+        );
+    }
+
+    #[test]
+    fn filter_conditions_anchor_ages_at_now() {
+        let now = "2025-02-13T12:00:00Z".parse().unwrap();
+        let args = fetch_args(&["--min-age", "1d", "--max-age", "2w"]);
+
+        assert_eq!(
+            filter_conditions(&args, now).unwrap(),
+            "1 = 1 and created_at <= '2025-02-12 12:00:00' and created_at >= '2025-01-30 12:00:00'"
+        );
+        let args = fetch_args(&["--min-age", "2w", "--max-age", "1d"]);
+        assert!(filter_conditions(&args, now).is_err());
+    }
+
+    #[test]
+    fn pages_of_issues_created_at_once_neither_overlap_nor_skip() {
+        let client = memory_client();
+        for id in 1..=5 {
+            issue(id, "a/b", "2024-01-01T00:00:00Z")
+                .insert(&client.database)
+                .unwrap();
+        }
+
+        let pages =
+            ["1", "2", "3"].map(|page| fetched_ids(&client, &["--page", page, "--page-num", "2"]));
+        assert_eq!(pages, [vec![5, 4], vec![3, 2], vec![1]]);
+        // The same page twice comes in the same order
+        assert_eq!(
+            fetched_ids(&client, &["--page", "2", "--page-num", "2"]),
+            [3, 2]
+        );
+    }
+
+    #[test]
+    fn cursor_page_holding_a_single_issue_is_sorted_without_a_tiebreaker() {
+        let client = memory_client();
+        for (id, day) in [(1, 1), (2, 2), (3, 3), (4, 4)] {
+            issue(id, "a/b", &format!("2024-01-0{}T00:00:00Z", day))
+                .insert(&client.database)
+                .unwrap();
+        }
+
+        assert_eq!(
+            fetched_ids(&client, &["--after-created", "2024-01-02T00:00:00Z"]),
+            [1]
+        );
+        assert_eq!(
+            fetched_ids(&client, &["--after-created", "2024-01-04T00:00:00Z"]),
+            [3, 2, 1]
+        );
+    }
+
+    #[test]
+    fn cursor_pages_continue_within_a_shared_created_at() {
+        let client = memory_client();
+        for (id, day) in [(1, 1), (2, 3), (3, 3), (4, 4)] {
+            issue(id, "a/b", &format!("2024-01-0{}T00:00:00Z", day))
+                .insert(&client.database)
+                .unwrap();
+        }
+
+        let mut cursor = vec![
+            "--after-created".to_string(),
+            "2024-02-01T00:00:00Z".to_string(),
+        ];
+        let mut seen = Vec::new();
+        loop {
+            let mut args = vec!["--page-num", "1"];
+            args.extend(cursor.iter().map(String::as_str));
+            let Some(issue) = client.fetch_issues(&fetch_args(&args)).unwrap().next() else {
+                break;
+            };
+            let issue = issue.unwrap();
+            seen.push(issue.id);
+            cursor = vec![
+                "--after-created".to_string(),
+                issue.created_at.to_rfc3339(),
+                "--after-id".to_string(),
+                issue.id.to_string(),
+            ];
+        }
+        assert_eq!(seen, [4, 3, 2, 1]);
+    }
+
+    #[test]
+    fn created_on_spans_midnight_to_midnight_utc() {
+        let client = memory_client();
+        for (id, created_at) in [
+            (1, "2024-03-09T23:59:59Z"),
+            (2, "2024-03-10T00:00:00Z"),
+            (3, "2024-03-10T23:59:59Z"),
+            (4, "2024-03-11T00:00:00Z"),
+        ] {
+            issue(id, "a/b", created_at)
+                .insert(&client.database)
+                .unwrap();
+        }
+
+        assert_eq!(
+            fetched_ids(&client, &["--created-on", "2024-03-10"]),
+            [3, 2]
+        );
+        assert_eq!(
+            filter_conditions(&fetch_args(&["--created-on", "2024-03-10"]), Utc::now()).unwrap(),
+            "1 = 1 and created_at >= '2024-03-10 00:00:00' and created_at < '2024-03-11 00:00:00'"
+        );
+    }
+
+    #[test]
+    fn today_starts_at_midnight_utc() {
+        let now = "2024-03-10T00:00:01Z".parse().unwrap();
+
+        assert_eq!(
+            filter_conditions(&fetch_args(&["--today"]), now).unwrap(),
+            "1 = 1 and created_at >= '2024-03-10 00:00:00'"
+        );
+    }
+
+    #[test]
+    fn label_removed_upstream_is_unlinked_on_the_next_insert() {
+        let client = memory_client();
+        let label = |id, name: &str| Label {
+            id,
+            name: name.to_string(),
+            ..Default::default()
+        };
+        let mut labeled = issue(1, "a/b", "2024-01-01T00:00:00Z");
+        labeled.labels = vec![label(10, "bug"), label(11, "urgent")];
+        labeled.insert(&client.database).unwrap();
+
+        labeled.labels = vec![label(10, "bug")];
+        labeled.insert(&client.database).unwrap();
+
+        let mut stored = client
+            .fetch_issues(&fetch_args(&[]))
+            .unwrap()
+            .next()
+            .unwrap()
+            .unwrap();
+        stored.load_labels(&client.database).unwrap();
+        let names = stored
+            .labels
+            .iter()
+            .map(|label| label.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["bug"]);
+        assert!(fetched_ids(&client, &["--label-name", "urgent"]).is_empty());
+    }
+
+    #[test]
+    fn emoji_and_very_long_titles_are_stored_unchanged() {
+        let client = memory_client();
+        let titles = [
+            "🚀🔥🐛👍🏽 crash when the title is 👨‍👩‍👧‍👦 only".to_string(),
+            "長いタイトル ".repeat(2000),
+        ];
+        for (id, title) in (1..).zip(&titles) {
+            let mut issue = issue(id, "a/b", "2024-01-01T00:00:00Z");
+            issue.title = title.clone();
+            issue.insert(&client.database).unwrap();
+        }
+
+        let mut stored = client
+            .fetch_issues(&fetch_args(&[]))
+            .unwrap()
+            .map(|issue| issue.unwrap())
+            .collect::<Vec<_>>();
+        stored.sort_by_key(|issue| issue.id);
+        let stored = stored
+            .into_iter()
+            .map(|issue| issue.title)
+            .collect::<Vec<_>>();
+        assert_eq!(stored, titles);
+    }
+
+    #[test]
+    fn owner_filter_keeps_every_repo_of_the_owner() {
+        let client = memory_client();
+        for (id, repo_name) in [
+            (1, "org/api"),
+            (2, "org/web"),
+            (3, "other/api"),
+            (4, "org2/api"),
+        ] {
+            issue(id, repo_name, &format!("2024-01-0{}T00:00:00Z", id))
+                .insert(&client.database)
+                .unwrap();
+        }
+
+        assert_eq!(fetched_ids(&client, &["--owner", "org"]), [2, 1]);
+    }
+
+    #[test]
+    fn excluded_repos_are_absent_from_the_results() {
+        let client = memory_client();
+        for (id, repo_name) in [
+            (1, "org/api"),
+            (2, "org/noisy"),
+            (3, "org/web"),
+            (4, "org/bot"),
+        ] {
+            issue(id, repo_name, &format!("2024-01-0{}T00:00:00Z", id))
+                .insert(&client.database)
+                .unwrap();
+        }
+
+        assert_eq!(
+            fetched_ids(
+                &client,
+                &[
+                    "--owner",
+                    "org",
+                    "--exclude-repo",
+                    "org/noisy",
+                    "--exclude-repo",
+                    "org/bot"
+                ]
+            ),
+            [3, 1]
+        );
+    }
+
+    fn issue_json(id: u64, created_at: &str) -> String {
+        format!(
+            r#"{{"id":{0},"number":{0},"title":"issue {0}","state":"open","user":{{"id":1,"login":"octocat"}},"labels":[],"created_at":"{1}"}}"#,
+            id, created_at
+        )
+    }
+
+    /// Serves `a/b` with issues 3 and 1 on the first page and `second_page` as the status of the next.
+    async fn pruning_client(second_page: u16) -> Client {
+        let mut client = memory_client();
+        client.retry_attempts(1);
+        client.api_url = mock_server(move |path| {
+            if path == "/repos/a/b" {
+                return (200, Vec::new(), r#"{"full_name":"a/b"}"#.to_string());
+            }
+            if path.ends_with("&page=1") {
+                let page = [
+                    issue_json(3, "2024-01-03T00:00:00Z"),
+                    issue_json(1, "2024-01-01T00:00:00Z"),
+                ];
+                return (200, Vec::new(), format!("[{}]", page.join(",")));
+            }
+            (second_page, Vec::new(), "[]".to_string())
+        })
+        .await;
+        Repo::from_str("a/b")
+            .unwrap()
+            .insert(&client.database)
+            .unwrap();
+        for id in 1..=3 {
+            issue(id, "a/b", &format!("2024-01-0{}T00:00:00Z", id))
+                .insert(&client.database)
+                .unwrap();
+        }
+
+        client
+    }
+
+    #[tokio::test]
+    async fn prune_missing_deletes_issues_gone_upstream() {
+        let client = pruning_client(200).await;
+
+        client
+            .update_issues(&UpdateArgs::parse_from([
+                "update",
+                "--quiet",
+                "--prune-missing",
+            ]))
+            .await
+            .unwrap();
+
+        assert_eq!(fetched_ids(&client, &[]), [3, 1]);
+    }
+
+    #[tokio::test]
+    async fn prune_missing_keeps_everything_when_the_sync_fails() {
+        let client = pruning_client(502).await;
+
+        let result = client
+            .update_issues(&UpdateArgs::parse_from([
+                "update",
+                "--quiet",
+                "--prune-missing",
+            ]))
+            .await;
+
+        assert!(result.is_err());
+        assert_eq!(fetched_ids(&client, &[]), [3, 2, 1]);
+    }
+
+    #[test]
+    fn create_after_includes_the_boundary_unless_exclusive() {
+        let client = memory_client();
+        for (id, created_at) in [
+            (1, "2024-03-10T11:59:59Z"),
+            (2, "2024-03-10T12:00:00Z"),
+            (3, "2024-03-10T12:00:01Z"),
+        ] {
+            issue(id, "a/b", created_at)
+                .insert(&client.database)
+                .unwrap();
+        }
+        let boundary = ["--create-after", "2024-03-10T12:00:00Z"];
+
+        assert_eq!(fetched_ids(&client, &boundary), [3, 2]);
+        assert_eq!(
+            fetched_ids(&client, &[&boundary[..], &["--exclusive"]].concat()),
+            [3]
+        );
+        // The last of the two flags wins
+        assert_eq!(
+            fetched_ids(
+                &client,
+                &[&boundary[..], &["--exclusive", "--inclusive"]].concat()
+            ),
+            [3, 2]
+        );
+    }
+
+    /// Answers with the statuses in turn, the last one repeatedly. Returns the url and the request count.
+    async fn flaky_server(statuses: &'static [u16]) -> (String, Arc<AtomicUsize>) {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+        let url = mock_server(move |_| {
+            let attempt = counter.fetch_add(1, Ordering::SeqCst);
+            let status = statuses[attempt.min(statuses.len() - 1)];
+            (status, Vec::new(), "{}".to_string())
+        })
+        .await;
+
+        (url, requests)
+    }
+
+    #[tokio::test]
+    async fn server_errors_are_retried_until_success() {
+        let client = memory_client();
+        let (url, requests) = flaky_server(&[502, 503, 200]).await;
+
+        let response = client.send(client.get(&url)).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn retries_stop_after_the_configured_attempts() {
+        let mut client = memory_client();
+        client.retry_attempts(2);
+        let (url, requests) = flaky_server(&[503]).await;
+
+        let response = client.send(client.get(&url)).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn client_errors_fail_fast() {
+        let client = memory_client();
+        let (url, requests) = flaky_server(&[404, 200]).await;
+
+        let response = client.send(client.get(&url)).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn label_name_resolves_to_the_labels_of_every_repo() {
+        let client = memory_client();
+        let label = |id, name: &str| Label {
+            id,
+            name: name.to_string(),
+            ..Default::default()
+        };
+        for (id, repo_name, labels) in [
+            (1, "a/x", vec![label(10, "Bug")]),
+            (2, "a/y", vec![label(20, "bug")]),
+            (3, "b/z", vec![label(30, "bug-report")]),
+            (4, "a/y", vec![label(10, "Bug"), label(20, "bug")]),
+        ] {
+            let mut labeled = issue(id, repo_name, &format!("2024-01-0{}T00:00:00Z", id));
+            labeled.labels = labels;
+            labeled.insert(&client.database).unwrap();
+        }
+
+        assert_eq!(client.label_ids_by_name("bug", false).unwrap(), [10, 20]);
+        assert_eq!(client.label_ids_by_name("bug", true).unwrap(), [20]);
+        assert!(client
+            .label_ids_by_name("feature", false)
+            .unwrap()
+            .is_empty());
+        assert_eq!(client.issues_with_any_label(&[10, 20]).unwrap(), [1, 2, 4]);
+        assert_eq!(fetched_ids(&client, &["--label-name", "BUG"]), [4, 2, 1]);
+    }
+
+    /// Far more ids than kite_sql handles in a single `in (...)` list.
+    const MANY_IDS: u64 = 12000;
+
+    #[test]
+    fn remove_issues_takes_a_very_large_id_set() {
+        let client = memory_client();
+        for id in [1, MANY_IDS / 2, MANY_IDS, MANY_IDS + 1] {
+            let mut labeled = issue(id, "a/b", "2024-01-01T00:00:00Z");
+            labeled.labels = vec![Label {
+                id: 10,
+                name: "bug".to_string(),
+                ..Default::default()
+            }];
+            labeled.insert(&client.database).unwrap();
+        }
+
+        client
+            .remove_issues(&(1..=MANY_IDS).collect::<Vec<_>>())
+            .unwrap();
+
+        assert_eq!(fetched_ids(&client, &[]), [MANY_IDS + 1]);
+        assert_eq!(client.issues_with_any_label(&[10]).unwrap(), [MANY_IDS + 1]);
+    }
+
+    #[test]
+    fn very_large_label_id_set_is_split_into_several_lists() {
+        let client = memory_client();
+        let mut labeled = issue(1, "a/b", "2024-01-01T00:00:00Z");
+        labeled.labels = vec![Label {
+            id: MANY_IDS - 1,
+            name: "bug".to_string(),
+            ..Default::default()
+        }];
+        labeled.insert(&client.database).unwrap();
+        issue(2, "a/b", "2024-01-02T00:00:00Z")
+            .insert(&client.database)
+            .unwrap();
+
+        let label_ids = (1..=MANY_IDS)
+            .map(|id| format!("--label-id={}", id))
+            .collect::<Vec<_>>();
+        let args = label_ids.iter().map(String::as_str).collect::<Vec<_>>();
+        assert_eq!(fetched_ids(&client, &args), [1]);
+    }
+
+    #[test]
+    fn repo_name_token_reads_the_token_env_of_the_stored_repo() {
+        let client =
+            Client::with_storage("", Some("global".to_string()), StorageKind::Memory).unwrap();
+        std::env::set_var("ISSUE_HUNTER_TEST_WORK_TOKEN", "work");
+        Repo {
+            token_env: Some("ISSUE_HUNTER_TEST_WORK_TOKEN".to_string()),
+            ..Repo::from_str("work/repo").unwrap()
+        }
+        .insert(&client.database)
+        .unwrap();
+        Repo::from_str("own/repo")
+            .unwrap()
+            .insert(&client.database)
+            .unwrap();
+
+        let token = |repo_name| client.repo_name_token(repo_name).unwrap();
+        assert_eq!(token("work/repo").as_deref(), Some("work"));
+        assert_eq!(token("own/repo").as_deref(), Some("global"));
+        assert_eq!(token("untracked/repo").as_deref(), Some("global"));
+    }
+
+    #[cfg(feature = "notify")]
+    #[test]
+    fn notify_label_matches_only_issues_not_stored_yet() {
+        let client = memory_client();
+        let args = UpdateArgs::parse_from(["update", "--notify-label", "Bug"]);
+        let mut labeled = issue(1, "a/b", "2024-01-01T00:00:00Z");
+        labeled.labels = vec![Label {
+            id: 10,
+            name: "bug".to_string(),
+            ..Default::default()
+        }];
+        let unlabeled = issue(2, "a/b", "2024-01-01T00:00:00Z");
+
+        let notified = |issue| client.new_with_notify_label(&args, issue).unwrap();
+        assert_eq!(notified(&labeled), Some("Bug"));
+        assert_eq!(notified(&unlabeled), None);
+
+        labeled.insert(&client.database).unwrap();
+        assert_eq!(notified(&labeled), None);
+    }
+
+    #[tokio::test]
+    async fn interrupted_update_resumes_from_the_checkpoint() {
+        let mut client = memory_client();
+        client.retry_attempts(1);
+        let second_page_hits = Arc::new(AtomicUsize::new(0));
+        let pages = Arc::new(std::sync::Mutex::new(Vec::new()));
+        client.api_url = {
+            let second_page_hits = second_page_hits.clone();
+            let pages = pages.clone();
+            mock_server(move |path| {
+                if path == "/repos/a/b" {
+                    return (200, Vec::new(), r#"{"full_name":"a/b"}"#.to_string());
+                }
+                pages.lock().unwrap().push(path.to_string());
+                let page = if path.ends_with("&page=1") {
+                    vec![
+                        issue_json(3, "2024-01-03T00:00:00Z"),
+                        issue_json(2, "2024-01-02T00:00:00Z"),
+                    ]
+                } else if path.ends_with("&page=2") {
+                    // The first run breaks off here
+                    if second_page_hits.fetch_add(1, Ordering::SeqCst) == 0 {
+                        return (502, Vec::new(), String::new());
+                    }
+                    vec![issue_json(1, "2024-01-01T00:00:00Z")]
+                } else {
+                    Vec::new()
+                };
+                (200, Vec::new(), format!("[{}]", page.join(",")))
+            })
+            .await
+        };
+        Repo::from_str("a/b")
+            .unwrap()
+            .insert(&client.database)
+            .unwrap();
+        let args = UpdateArgs::parse_from([
+            "update",
+            "--quiet",
+            "--create-after",
+            "2000-01-01T00:00:00Z",
+        ]);
+
+        assert!(client.update_issues(&args).await.is_err());
+        let checkpoint = Checkpoint::load(&client.database, "a/b").unwrap().unwrap();
+        assert_eq!(checkpoint.page, 2);
+        assert_eq!(checkpoint.sort_by, "created");
+        assert_eq!(fetched_ids(&client, &[]), [3, 2]);
+
+        pages.lock().unwrap().clear();
+        client.update_issues(&args).await.unwrap();
+        let pages = pages.lock().unwrap();
+        assert!(pages[0].ends_with("&page=2"), "resumed at {}", pages[0]);
+        assert!(!pages.iter().any(|path| path.ends_with("&page=1")));
+        assert!(Checkpoint::load(&client.database, "a/b").unwrap().is_none());
+        assert_eq!(fetched_ids(&client, &[]), [3, 2, 1]);
+    }
+
+    #[tokio::test]
+    async fn unchanged_pages_are_skipped_with_their_etag() {
+        let mut client = memory_client();
+        let revalidated = Arc::new(AtomicUsize::new(0));
+        client.api_url = {
+            let revalidated = revalidated.clone();
+            mock_server_with_headers(move |path, request| {
+                if path == "/repos/a/b" {
+                    return (200, Vec::new(), r#"{"full_name":"a/b"}"#.to_string());
+                }
+                if !path.ends_with("&page=1") {
+                    return (200, Vec::new(), "[]".to_string());
+                }
+                if request.to_lowercase().contains("if-none-match: \"v1\"") {
+                    revalidated.fetch_add(1, Ordering::SeqCst);
+                    return (304, Vec::new(), String::new());
+                }
+                // Were the page fetched again, the new issue 3 would be stored
+                let page = if revalidated.load(Ordering::SeqCst) == 0 {
+                    vec![issue_json(2, "2024-01-02T00:00:00Z")]
+                } else {
+                    vec![issue_json(3, "2024-01-03T00:00:00Z")]
+                };
+                let etag = vec![("ETag", "\"v1\"".to_string())];
+                (200, etag, format!("[{}]", page.join(",")))
+            })
+            .await
+        };
+        Repo::from_str("a/b")
+            .unwrap()
+            .insert(&client.database)
+            .unwrap();
+        let args = UpdateArgs::parse_from([
+            "update",
+            "--quiet",
+            "--create-after",
+            "2000-01-01T00:00:00Z",
+        ]);
+
+        client.update_issues(&args).await.unwrap();
+        let url = format!(
+            "{}/repos/a/b/issues?sort=created&direction=desc&page=1",
+            client.api_url
+        );
+        let etag = ETag::load(&client.database, &url).unwrap().unwrap();
+        assert_eq!(etag.etag, "\"v1\"");
+        assert_eq!(
+            etag.oldest_created.to_rfc3339(),
+            "2024-01-02T00:00:00+00:00"
+        );
+
+        client.update_issues(&args).await.unwrap();
+        assert_eq!(revalidated.load(Ordering::SeqCst), 1);
+        assert_eq!(client.request_stats().not_modified, 1);
+        assert_eq!(fetched_ids(&client, &[]), [2]);
+    }
+
+    /// The `created:` range of a search request, `None` for the query as given.
+    fn searched_window(path: &str) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+        let query = path
+            .split(['?', '&'])
+            .find_map(|pair| pair.strip_prefix("q="))
+            .unwrap()
+            .replace('+', " ")
+            .replace("%3A", ":");
+        let (from, to) = query.split_once("created:")?.1.split_once("..")?;
+
+        Some((from.parse().unwrap(), to.parse().unwrap()))
+    }
+
+    /// Answers as if 1001 issues were created in the second of issue 1, issue 2 on its own later,
+    /// and lists issue 3 in every answer as the search index may while it catches up.
+    #[tokio::test]
+    async fn searches_over_1000_results_are_split_into_creation_windows() {
+        let mut client = memory_client();
+        let crowded: DateTime<Utc> = "2020-01-01T00:00:00Z".parse().unwrap();
+        let single: DateTime<Utc> = "2022-06-01T00:00:00Z".parse().unwrap();
+        let windows = Arc::new(std::sync::Mutex::new(Vec::new()));
+        client.api_url = {
+            let windows = windows.clone();
+            mock_server_with_headers(move |path, request| {
+                let host = request
+                    .lines()
+                    .find_map(|line| {
+                        line.to_lowercase()
+                            .strip_prefix("host: ")
+                            .map(str::to_string)
+                    })
+                    .unwrap();
+                let item = |id, created_at: DateTime<Utc>| {
+                    let issue = issue_json(id, &created_at.to_rfc3339());
+                    format!(
+                        r#"{},"repository_url":"http://{}/repos/a/b"}}"#,
+                        issue.strip_suffix('}').unwrap(),
+                        host
+                    )
+                };
+                let window = searched_window(path);
+                windows.lock().unwrap().push(window);
+                let within =
+                    |created| window.map_or(true, |(from, to)| from <= created && created <= to);
+                let mut total_count = 1;
+                let mut items = vec![item(3, "2021-01-01T00:00:00Z".parse().unwrap())];
+                if within(crowded) {
+                    total_count += 1001;
+                    items.push(item(1, crowded));
+                }
+                if within(single) {
+                    total_count += 1;
+                    items.push(item(2, single));
+                }
+                let body = format!(
+                    r#"{{"total_count":{},"items":[{}]}}"#,
+                    total_count,
+                    items.join(",")
+                );
+                (200, Vec::new(), body)
+            })
+            .await
+        };
+
+        let issues = client
+            .search_github(&SearchGithubArgs::parse_from(["search-github", "is:issue"]))
+            .await
+            .unwrap();
+
+        let mut ids = issues.iter().map(|issue| issue.id).collect::<Vec<_>>();
+        ids.sort();
+        assert_eq!(ids, [1, 2, 3]);
+        let windows = windows.lock().unwrap();
+        assert_eq!(windows[0], None);
+        assert!(windows.iter().flatten().all(|(from, to)| from <= to));
+        // The crowded second is served capped instead of being split any further
+        let floor = windows
+            .iter()
+            .flatten()
+            .filter(|(from, to)| *from <= crowded && crowded <= *to)
+            .min_by_key(|(from, to)| *to - *from)
+            .unwrap();
+        assert!(floor.1 - floor.0 <= TimeDelta::seconds(1));
+    }
+
+    #[tokio::test]
+    async fn rate_limited_searches_are_tried_again() {
+        let mut client = memory_client();
+        let requests = Arc::new(AtomicUsize::new(0));
+        client.api_url = {
+            let requests = requests.clone();
+            mock_server(move |_| match requests.fetch_add(1, Ordering::SeqCst) {
+                0 => (
+                    403,
+                    vec![("Retry-After", "0".to_string())],
+                    r#"{"message":"secondary rate limit"}"#.to_string(),
+                ),
+                1 => (
+                    429,
+                    vec![
+                        ("x-ratelimit-remaining", "0".to_string()),
+                        ("x-ratelimit-reset", Utc::now().timestamp().to_string()),
+                    ],
+                    String::new(),
+                ),
+                _ => (
+                    200,
+                    Vec::new(),
+                    r#"{"total_count":0,"items":[]}"#.to_string(),
+                ),
+            })
+            .await
+        };
+        let args = SearchGithubArgs::parse_from(["search-github", "is:issue"]);
+
+        assert!(client.search_github(&args).await.unwrap().is_empty());
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn forbidden_searches_without_a_rate_limit_fail_fast() {
+        let mut client = memory_client();
+        let requests = Arc::new(AtomicUsize::new(0));
+        client.api_url = {
+            let requests = requests.clone();
+            mock_server(move |_| {
+                requests.fetch_add(1, Ordering::SeqCst);
+                (403, Vec::new(), String::new())
+            })
+            .await
+        };
+        let args = SearchGithubArgs::parse_from(["search-github", "is:issue"]);
+
+        assert!(matches!(
+            client.search_github(&args).await,
+            Err(Error::Status(StatusCode::FORBIDDEN))
+        ));
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+    }
+
+    /// Serves pull request 1 of `a/b` as an issue, its details answer with `status`.
+    async fn pull_request_client(status: u16) -> Client {
+        let mut client = memory_client();
+        client.retry_attempts(1);
+        client.api_url = mock_server_with_headers(move |path, request| {
+            if path == "/repos/a/b/pulls/1" {
+                let detail = r#"{"merged":true,"head":{"ref":"feature"},"base":{"ref":"main"}}"#;
+                return (status, Vec::new(), detail.to_string());
+            }
+            let host = request
+                .lines()
+                .find_map(|line| {
+                    line.to_lowercase()
+                        .strip_prefix("host: ")
+                        .map(str::to_string)
+                })
+                .unwrap();
+            let issue = issue_json(1, "2024-01-01T00:00:00Z");
+            let body = format!(
+                r#"{},"pull_request":{{"url":"http://{}/repos/a/b/pulls/1","merged_at":null}}}}"#,
+                issue.strip_suffix('}').unwrap(),
+                host
+            );
+            (200, Vec::new(), body)
+        })
+        .await;
+
+        client
+    }
+
+    fn stored_prs(client: &Client) -> Vec<PullRequest> {
+        client
+            .fetch_prs(&fetch_args(&[]))
+            .unwrap()
+            .map(Result::unwrap)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn pull_request_without_details_is_stored_without_branches() {
+        let client = pull_request_client(404).await;
+
+        assert!(client
+            .refresh(&RefreshArgs::parse_from(["refresh", "a/b", "1"]))
+            .await
+            .unwrap());
+
+        let prs = stored_prs(&client);
+        assert_eq!(prs.len(), 1);
+        assert_eq!(
+            (prs[0].head.as_deref(), prs[0].base.as_deref()),
+            (None, None)
+        );
+
+        let client = pull_request_client(200).await;
+        client
+            .refresh(&RefreshArgs::parse_from(["refresh", "a/b", "1"]))
+            .await
+            .unwrap();
+        let prs = stored_prs(&client);
+        assert_eq!(
+            (prs[0].head.as_deref(), prs[0].base.as_deref()),
+            (Some("feature"), Some("main"))
+        );
+    }
+
+    #[tokio::test]
+    async fn rejected_token_fails_the_pull_request_instead_of_degrading_it() {
+        let client = pull_request_client(401).await;
+
+        let result = client
+            .refresh(&RefreshArgs::parse_from(["refresh", "a/b", "1"]))
+            .await;
+
+        assert!(matches!(
+            result,
+            Err(Error::Status(StatusCode::UNAUTHORIZED))
+        ));
+        assert!(stored_prs(&client).is_empty());
+    }
+
+    #[test]
+    fn second_open_of_a_database_reports_it_locked() {
+        let path = std::env::temp_dir().join(format!("issue-hunter-locked-{}", std::process::id()));
+        let _removed_last = TempDir(path.clone());
+        let _first = Client::with_storage(&path, None, StorageKind::Rocksdb).unwrap();
+
+        let second = Client::with_storage(&path, None, StorageKind::Rocksdb);
+
+        assert!(matches!(
+            second,
+            Err(Error::DatabaseLocked(locked)) if locked == path.display().to_string()
+        ));
+    }
+}
@@ -0,0 +1,245 @@
+use crate::args::FetchArgs;
+use crate::error::Error;
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+pub const CONFIG_FILE: &str = "config.toml";
+const APP_DIR: &str = "issue-hunter";
+
+/// Where the database lives, e.g. `$XDG_DATA_HOME/issue-hunter` on Linux,
+/// `~/Library/Application Support/issue-hunter` on macOS and `%APPDATA%\\issue-hunter` on Windows.
+pub fn data_dir() -> Result<PathBuf, Error> {
+    dirs::data_dir()
+        .map(|dir| dir.join(APP_DIR))
+        .ok_or_else(|| Error::Config("no data directory found for this system".to_string()))
+}
+
+/// Where `config.toml` lives, e.g. `$XDG_CONFIG_HOME/issue-hunter` on Linux.
+pub fn config_dir() -> Result<PathBuf, Error> {
+    dirs::config_dir()
+        .map(|dir| dir.join(APP_DIR))
+        .ok_or_else(|| Error::Config("no config directory found for this system".to_string()))
+}
+
+/// Moves the `~/issue-hunter` of older versions to the data directory, and its config file to
+/// the config directory. Nothing happens once the data directory exists.
+/// Returns the directory migrated from.
+pub fn migrate_legacy_dir(data_dir: &Path, config_dir: &Path) -> Result<Option<PathBuf>, Error> {
+    let Some(legacy_dir) = dirs::home_dir().map(|dir| dir.join(APP_DIR)) else {
+        return Ok(None);
+    };
+    if !legacy_dir.is_dir() || data_dir.exists() || legacy_dir == data_dir {
+        return Ok(None);
+    }
+    if let Some(parent) = data_dir.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::rename(&legacy_dir, data_dir).map_err(|err| {
+        Error::Config(format!(
+            "could not move {} to {}, please move it by hand: {}",
+            legacy_dir.display(),
+            data_dir.display(),
+            err
+        ))
+    })?;
+    let legacy_config = data_dir.join(CONFIG_FILE);
+    let config = config_dir.join(CONFIG_FILE);
+    if legacy_config.is_file() && !config.exists() {
+        fs::create_dir_all(config_dir)?;
+        fs::rename(legacy_config, config)?;
+    }
+
+    Ok(Some(legacy_dir))
+}
+
+/// Settings read from `config.toml` in the config directory, every key is optional.
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct Config {
+    /// Used by `fetch` and `discussions` when `--page-num` is not given
+    pub default_page_size: Option<usize>,
+    /// Used by `fetch` when `--repo-name` is not given, `ISSUE_HUNTER_DEFAULT_REPO` wins over it
+    pub default_repo: Option<String>,
+    /// Run by `update` unless `--on-complete` is given
+    pub on_complete: Option<String>,
+    pub presets: HashMap<String, Preset>,
+}
+
+/// A saved set of `fetch` filters, e.g. in `config.toml`:
+///
+/// ```toml
+/// [presets.my-bugs]
+/// state = "open"
+/// label_name = "bug"
+/// ```
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Preset {
+    pub repo_name: Option<String>,
+    pub owner: Option<String>,
+    pub label_name: Option<String>,
+    pub state: Option<String>,
+    pub unlabeled: bool,
+    /// Only issues created within the last given days
+    pub newer_than_days: Option<i64>,
+    /// Only issues created more than the given days ago
+    pub older_than_days: Option<i64>,
+}
+
+impl Config {
+    /// Loads the config of the config directory, a missing file is an empty config.
+    pub fn load(dir: &Path) -> Result<Self, Error> {
+        let path = dir.join(CONFIG_FILE);
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Config::default()),
+            Err(err) => return Err(err.into()),
+        };
+
+        toml::from_str(&content)
+            .map_err(|err| Error::Config(format!("{}: {}", path.display(), err)))
+    }
+
+    /// Presets of the config file shadow the built-in ones of the same name.
+    pub fn preset(&self, name: &str) -> Result<Preset, Error> {
+        self.presets
+            .get(name)
+            .cloned()
+            .or_else(|| Preset::builtin(name))
+            .ok_or_else(|| Error::NotFound(format!("Preset: '{}'", name)))
+    }
+}
+
+impl Preset {
+    pub fn builtin(name: &str) -> Option<Self> {
+        match name {
+            "recent-open" => Some(Preset {
+                state: Some("open".to_string()),
+                newer_than_days: Some(7),
+                ..Default::default()
+            }),
+            "stale-unlabeled" => Some(Preset {
+                state: Some("open".to_string()),
+                unlabeled: true,
+                older_than_days: Some(30),
+                ..Default::default()
+            }),
+            _ => None,
+        }
+    }
+
+    /// Fills the filters of `args` from the preset, filters given on the command line win.
+    pub fn expand(&self, args: &mut FetchArgs, now: DateTime<Utc>) {
+        let days_ago = |days| now - TimeDelta::days(days);
+
+        if args.repo_name.is_none() {
+            args.repo_name.clone_from(&self.repo_name);
+        }
+        if args.owner.is_none() {
+            args.owner.clone_from(&self.owner);
+        }
+        if args.label_name.is_none() {
+            args.label_name.clone_from(&self.label_name);
+        }
+        if args.state.is_none() {
+            args.state.clone_from(&self.state);
+        }
+        args.unlabeled |= self.unlabeled;
+        if args.created_on.is_some() {
+            return;
+        }
+        if args.create_after.is_none() && !args.today {
+            args.create_after = self.newer_than_days.map(days_ago);
+        }
+        if args.create_before.is_none() {
+            args.create_before = self.older_than_days.map(days_ago);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    fn now() -> DateTime<Utc> {
+        "2024-03-31T12:00:00Z".parse().unwrap()
+    }
+
+    fn expanded(preset: &str, args: &[&str]) -> FetchArgs {
+        let mut args = FetchArgs::parse_from(["fetch"].iter().chain(args));
+        Preset::builtin(preset).unwrap().expand(&mut args, now());
+        args
+    }
+
+    #[test]
+    fn recent_open_keeps_the_open_issues_of_the_last_week() {
+        let args = expanded("recent-open", &[]);
+        assert_eq!(args.state.as_deref(), Some("open"));
+        assert_eq!(
+            args.create_after,
+            Some("2024-03-24T12:00:00Z".parse().unwrap())
+        );
+        assert_eq!(args.create_before, None);
+        assert!(!args.unlabeled);
+    }
+
+    #[test]
+    fn stale_unlabeled_keeps_the_unlabeled_open_issues_older_than_a_month() {
+        let args = expanded("stale-unlabeled", &[]);
+        assert_eq!(args.state.as_deref(), Some("open"));
+        assert_eq!(args.create_after, None);
+        assert_eq!(
+            args.create_before,
+            Some("2024-03-01T12:00:00Z".parse().unwrap())
+        );
+        assert!(args.unlabeled);
+    }
+
+    #[test]
+    fn command_line_filters_win_over_the_preset() {
+        let args = expanded(
+            "recent-open",
+            &[
+                "--state",
+                "closed",
+                "--create-after",
+                "2024-01-01T00:00:00Z",
+            ],
+        );
+        assert_eq!(args.state.as_deref(), Some("closed"));
+        assert_eq!(
+            args.create_after,
+            Some("2024-01-01T00:00:00Z".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn created_on_and_today_suppress_the_age_filters() {
+        let args = expanded("stale-unlabeled", &["--created-on", "2024-03-02"]);
+        assert_eq!(args.create_before, None);
+        assert!(args.unlabeled);
+
+        let args = expanded("recent-open", &["--today"]);
+        assert_eq!(args.create_after, None);
+        assert_eq!(args.state.as_deref(), Some("open"));
+    }
+
+    #[test]
+    fn config_presets_shadow_the_builtin_ones() {
+        let config = toml::from_str::<Config>(
+            "[presets.recent-open]\nstate = \"closed\"\nlabel_name = \"bug\"\n",
+        )
+        .unwrap();
+
+        let preset = config.preset("recent-open").unwrap();
+        assert_eq!(preset.state.as_deref(), Some("closed"));
+        assert_eq!(preset.newer_than_days, None);
+        assert!(config.preset("stale-unlabeled").unwrap().unlabeled);
+        assert!(matches!(config.preset("missing"), Err(Error::NotFound(_))));
+    }
+}
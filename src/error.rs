@@ -0,0 +1,30 @@
+use kite_sql::errors::DatabaseError;
+use reqwest::StatusCode;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("http: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("request failed with status: {0}")]
+    Status(StatusCode),
+    #[error("GitHub rate limit exceeded, resets at {0}. Set GITHUB_TOKEN for a higher limit")]
+    RateLimited(String),
+    #[error("database: {0}")]
+    Database(#[from] DatabaseError),
+    #[error("database rejected the query of the filters: {0}. {1}, `--explain` prints the query")]
+    Query(DatabaseError, String),
+    #[error("{0} not found")]
+    NotFound(String),
+    #[error("Label: '{0}' not found, did you mean {1}?")]
+    LabelSuggestion(String, String),
+    #[error("{0}")]
+    Parse(String),
+    #[error("{0}")]
+    Config(String),
+    #[error("another instance is running, {0} is locked")]
+    Locked(String),
+    #[error("issue-hunter database is already open by another process, wait for it to finish or close it. Database: {0}")]
+    DatabaseLocked(String),
+    #[error("io: {0}")]
+    Io(#[from] std::io::Error),
+}
@@ -0,0 +1,34 @@
+//! Collects issues of multiple GitHub repositories into a local database.
+pub mod args;
+pub mod bean;
+pub mod client;
+pub mod config;
+pub mod error;
+pub mod lock;
+pub mod model;
+
+use kite_sql::db::Database;
+use kite_sql::storage::rocksdb::RocksStorage;
+use kite_sql::types::value::DataValue;
+
+pub use client::Client;
+pub use error::Error;
+
+pub type SqlBase = Database<RocksStorage>;
+
+/// Reads an id selected by a raw query, ids are stored as `BIGINT`.
+pub(crate) fn id_value(value: &DataValue) -> u64 {
+    value.i64().unwrap() as u64
+}
+
+pub(crate) fn escape_sql_string(input: &str) -> String {
+    input.replace("'", "''")
+}
+
+/// Escapes a `like` pattern so `%` and `_` match literally, to be used with `escape '\\'`.
+pub(crate) fn escape_like(input: &str) -> String {
+    escape_sql_string(input)
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
@@ -0,0 +1,45 @@
+use crate::error::Error;
+use fs2::FileExt;
+use std::fs::{self, File};
+use std::path::Path;
+
+pub const LOCK_FILE: &str = "ih.lock";
+
+/// An advisory lock on the data directory, released when dropped or when the process exits.
+pub struct DirLock {
+    file: File,
+}
+
+impl DirLock {
+    /// Taken by commands writing to the database, fails while any other instance holds the lock.
+    pub fn exclusive(dir: &Path) -> Result<Self, Error> {
+        Self::acquire(dir, FileExt::try_lock_exclusive)
+    }
+
+    /// Taken by readers, fails only while a writer holds the lock.
+    pub fn shared(dir: &Path) -> Result<Self, Error> {
+        Self::acquire(dir, FileExt::try_lock_shared)
+    }
+
+    fn acquire(dir: &Path, try_lock: fn(&File) -> std::io::Result<()>) -> Result<Self, Error> {
+        fs::create_dir_all(dir)?;
+        let path = dir.join(LOCK_FILE);
+        let file = File::create(&path)?;
+        // Fail fast instead of blocking behind a long update
+        try_lock(&file).map_err(|err| {
+            if err.raw_os_error() == fs2::lock_contended_error().raw_os_error() {
+                Error::Locked(path.display().to_string())
+            } else {
+                err.into()
+            }
+        })?;
+
+        Ok(DirLock { file })
+    }
+}
+
+impl Drop for DirLock {
+    fn drop(&mut self) {
+        let _ = FileExt::unlock(&self.file);
+    }
+}
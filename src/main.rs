@@ -1,328 +1,1076 @@
 use anyhow::anyhow;
-use chrono::{DateTime, Datelike, NaiveDateTime, TimeZone, Utc};
-use clap::{Parser, Subcommand};
-use kite_sql::db::{DataBaseBuilder, Database, ResultIter};
-use kite_sql::implement_from_tuple;
-use kite_sql::storage::rocksdb::RocksStorage;
-use kite_sql::types::value::DataValue;
+use chrono::{DateTime, Local, NaiveDate, NaiveTime, TimeDelta, Utc};
+use clap::{CommandFactory, Parser, Subcommand};
+use clap_complete::Shell;
+use dialoguer::{Confirm, Input};
+use issue_hunter::args::{
+    AddReposArgs, AgeHistogramArgs, Bucket, CheckArgs, DiffArgs, DiscussionsArgs, EditRepoArgs,
+    ExportArgs, FetchArgs, Format, ImportJsonArgs, ImportStarredArgs, NoteArgs, RefreshArgs,
+    RefreshStatesArgs, RemoveIssuesArgs, ReposArgs, SearchGithubArgs, SeriesFormat,
+    SetTokenEnvArgs, ShowArgs, StorageKind, TimeseriesArgs, UpdateArgs,
+};
+use issue_hunter::config::{self, Config};
+use issue_hunter::lock::DirLock;
+use issue_hunter::model::{parse_fields, Issue, IssueChange, Label, Problem, Repo, ISSUE_FIELDS};
+use issue_hunter::{Client, Error};
+use prettytable::cell::Cell;
 use prettytable::{row, Table};
-use serde::Deserialize;
-use std::cmp::min;
-use std::fmt::Write;
-
-type SqlBase = Database<RocksStorage>;
-
-#[derive(Deserialize, Debug, Default)]
-struct Issue {
-    id: u64,
-    number: u64,
-    title: String,
-    state: String,
-    #[serde(skip)]
-    repo_name: String,
-    #[serde(skip)]
-    user_id: u64,
-    user: User,
-    labels: Vec<Label>,
-    created_at: DateTime<Utc>,
-}
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, IsTerminal, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::process::{self, Stdio};
+
+fn humanize_duration(duration: TimeDelta) -> String {
+    let (count, unit) = if duration.num_days() > 0 {
+        (duration.num_days(), "day")
+    } else if duration.num_hours() > 0 {
+        (duration.num_hours(), "hour")
+    } else if duration.num_minutes() > 0 {
+        (duration.num_minutes(), "minute")
+    } else {
+        return "just now".to_string();
+    };
 
-implement_from_tuple!(
-    Issue, (
-        id: u64 => |inner: &mut Issue, value: DataValue| {
-            inner.id = value.u64().unwrap();
-        },
-        number: u64 => |inner: &mut Issue, value: DataValue| {
-            inner.number = value.u64().unwrap();
-        },
-        title: String => |inner: &mut Issue, value: DataValue| {
-            inner.title = value.utf8().unwrap().to_string();
-        },
-        state: String => |inner: &mut Issue, value: DataValue| {
-            inner.state = value.utf8().unwrap().to_string();
-        },
-        repo_name: String => |inner: &mut Issue, value: DataValue| {
-            inner.repo_name = value.utf8().unwrap().to_string();
-        },
-        user_id: u64 => |inner: &mut Issue, value: DataValue| {
-            inner.user_id = value.u64().unwrap();
-        },
-        created_at: NaiveDateTime => |inner: &mut Issue, value: DataValue| {
-            inner.created_at = value.datetime().unwrap().and_utc();
-        }
+    format!(
+        "{} {}{} ago",
+        count,
+        unit,
+        if count == 1 { "" } else { "s" }
     )
-);
+}
+
+const DEFAULT_REPO_ENV: &str = "ISSUE_HUNTER_DEFAULT_REPO";
 
-struct IssueLabelLink {
-    issue_id: u64,
-    label_id: u64,
+/// `5 labels` for the Labels column of `--label-summary`, empty without labels like the full list.
+fn label_summary(count: usize) -> String {
+    match count {
+        0 => String::new(),
+        1 => "1 label".to_string(),
+        count => format!("{} labels", count),
+    }
 }
 
-#[derive(Deserialize, Debug, Default)]
-struct User {
-    id: u64,
-    login: String,
+/// Applies the config file to the args, flags given on the command line win.
+/// The repo is taken from `--repo-name`, the preset, `ISSUE_HUNTER_DEFAULT_REPO` and
+/// `default_repo` of the config file in that order, unless `--all-repos` is given.
+fn with_config(config: &Config, args: &FetchArgs) -> Result<FetchArgs, Error> {
+    let mut args = args.clone();
+    if let Some(name) = &args.preset {
+        config.preset(name)?.expand(&mut args, Utc::now());
+    }
+    if args.page_num.is_none() {
+        args.page_num = config.default_page_size;
+    }
+    if args.repo_name.is_none() && !args.all_repos {
+        args.repo_name = std::env::var(DEFAULT_REPO_ENV)
+            .ok()
+            .filter(|repo_name| !repo_name.is_empty())
+            .or_else(|| config.default_repo.clone());
+    }
+
+    Ok(args)
 }
 
-implement_from_tuple!(
-    User, (
-        id: u64 => |inner: &mut User, value: DataValue| {
-            inner.id = value.u64().unwrap();
-        },
-        login: String => |inner: &mut User, value: DataValue| {
-            inner.login = value.utf8().unwrap().to_string();
-        }
-    )
-);
+/// Asks for the common filters on the terminal, an empty answer clears the filter.
+fn prompt_filters(args: &mut FetchArgs) -> anyhow::Result<()> {
+    fn prompt(prompt: &str, initial: Option<String>) -> anyhow::Result<Option<String>> {
+        let answer: String = Input::new()
+            .with_prompt(prompt)
+            .with_initial_text(initial.unwrap_or_default())
+            .allow_empty(true)
+            .interact_text()?;
+        let answer = answer.trim();
+
+        Ok((!answer.is_empty()).then(|| answer.to_string()))
+    }
+    fn prompt_date(
+        prompt_text: &str,
+        initial: Option<DateTime<Utc>>,
+    ) -> anyhow::Result<Option<DateTime<Utc>>> {
+        let initial = initial.map(|datetime| datetime.date_naive().to_string());
+        let Some(answer) = prompt(prompt_text, initial)? else {
+            return Ok(None);
+        };
+        let date = NaiveDate::parse_from_str(&answer, "%Y-%m-%d")
+            .map_err(|err| anyhow!("{}: {}", answer, err))?;
+
+        Ok(Some(date.and_time(NaiveTime::MIN).and_utc()))
+    }
 
-#[derive(Deserialize, Hash, Debug, Default, PartialEq, Eq)]
-struct Label {
-    id: u64,
-    name: String,
-    description: Option<String>,
+    args.repo_name = prompt("Repository (owner/name)", args.repo_name.take())?;
+    args.state = prompt("State (open, closed or all)", args.state.take())?;
+    args.label_name = prompt("Label", args.label_name.take())?;
+    args.create_after = prompt_date("Created after (YYYY-MM-DD)", args.create_after)?;
+    args.create_before = prompt_date("Created before (YYYY-MM-DD)", args.create_before)?;
+
+    Ok(())
 }
 
-implement_from_tuple!(
-    Label, (
-        id: u64 => |inner: &mut Label, value: DataValue| {
-            inner.id = value.u64().unwrap();
-        },
-        name: String => |inner: &mut Label, value: DataValue| {
-            inner.name = value.utf8().unwrap().to_string();
-        },
-        description: String => |inner: &mut Label, value: DataValue| {
-            inner.description = value.utf8().map(|s| s.to_string());
+/// Moves every sub-issue right behind its parent and marks it in the title,
+/// issues whose parent is not in the list stay where they are.
+fn nest_issues(issues: Vec<Issue>) -> Vec<Issue> {
+    fn push_with_children(
+        nested: &mut Vec<Issue>,
+        children: &mut HashMap<(String, u64), Vec<Issue>>,
+        mut issue: Issue,
+        depth: usize,
+    ) {
+        let key = (issue.repo_name.clone(), issue.number);
+        if depth > 0 {
+            issue.title = format!("{}└ {}", "  ".repeat(depth - 1), issue.title);
         }
-    )
-);
-
-#[derive(Deserialize, Parser, Debug, Default)]
-struct Repo {
-    #[clap(name = "owner", long)]
-    owner_name: String,
-    #[clap(long)]
-    name: String,
-}
+        nested.push(issue);
+        for child in children.remove(&key).unwrap_or_default() {
+            push_with_children(nested, children, child, depth + 1);
+        }
+    }
+
+    let keys = issues
+        .iter()
+        .map(|issue| (issue.repo_name.clone(), issue.number))
+        .collect::<HashSet<_>>();
+    let mut roots = Vec::new();
+    let mut children: HashMap<_, Vec<_>> = HashMap::new();
+    for issue in issues {
+        match issue.parent().filter(|parent| keys.contains(parent)) {
+            Some(parent) => children.entry(parent).or_default().push(issue),
+            None => roots.push(issue),
+        }
+    }
 
-impl Repo {
-    fn full_name(&self) -> String {
-        format!("{}/{}", self.owner_name, self.name)
+    let mut nested = Vec::with_capacity(keys.len());
+    for root in roots {
+        push_with_children(&mut nested, &mut children, root, 0);
     }
+    // Cycles have no root, they are appended flat rather than dropped
+    nested.extend(children.into_values().flatten());
+
+    nested
 }
 
-implement_from_tuple!(
-    Repo, (
-        owner_name: String => |inner: &mut Repo, value: DataValue| {
-            inner.owner_name = value.utf8().unwrap().to_string();
-        },
-        name: String => |inner: &mut Repo, value: DataValue| {
-            inner.name = value.utf8().unwrap().to_string();
-        }
-    )
-);
+/// Every command deleting stored data asks here first, `--yes` answers for it.
+/// Without a terminal to ask on, only `--yes` confirms.
+fn confirm(yes: bool, prompt: impl Into<String>) -> anyhow::Result<bool> {
+    let prompt = prompt.into();
+    if yes {
+        return Ok(true);
+    }
+    if !io::stdin().is_terminal() {
+        return Err(anyhow!(
+            "{} Pass --yes to confirm without a terminal",
+            prompt
+        ));
+    }
 
-trait Bean {
-    fn insert(&self, database: &SqlBase) -> anyhow::Result<()>;
-    fn delete(&self, database: &SqlBase) -> anyhow::Result<()>;
+    Ok(Confirm::new()
+        .with_prompt(prompt)
+        .default(false)
+        .interact()?)
 }
 
-impl Bean for Label {
-    fn insert(&self, database: &SqlBase) -> anyhow::Result<()> {
-        database
-            .run(format!(
-                "insert overwrite labels values({}, '{}', '{}');",
-                self.id,
-                escape_sql_string(&self.name),
-                self.description
-                    .as_ref()
-                    .map(|s| escape_sql_string(s))
-                    .unwrap_or("null".to_string()),
-            ))?
-            .done()?;
+/// `--null-as` for a table cell, which is missing when empty.
+fn or_null(value: String, null_as: Option<&str>) -> String {
+    match null_as {
+        Some(token) if value.is_empty() => token.to_string(),
+        _ => value,
+    }
+}
 
-        Ok(())
+/// `--null-as` for the json output, replacing the nulls of the top-level fields.
+fn replace_nulls(object: &mut serde_json::Value, null_as: Option<&str>) {
+    let (Some(token), Some(object)) = (null_as, object.as_object_mut()) else {
+        return;
+    };
+    for value in object.values_mut().filter(|value| value.is_null()) {
+        *value = serde_json::Value::from(token);
     }
+}
 
-    fn delete(&self, database: &SqlBase) -> anyhow::Result<()> {
-        database
-            .run(format!("delete from labels where id = {};", self.id))?
-            .done()?;
+/// Narrowest title the terminal width default cuts to.
+const MIN_TITLE_WIDTH: usize = 20;
+
+/// `--title-width`, else a third of the terminal width. `None` when titles stay whole,
+/// e.g. on 0 or when stdout is not a terminal.
+fn title_width(args: &FetchArgs) -> Option<usize> {
+    args.title_width
+        .or_else(|| {
+            terminal_size::terminal_size()
+                .map(|(width, _)| (width.0 as usize / 3).max(MIN_TITLE_WIDTH))
+        })
+        .filter(|width| *width > 0)
+}
 
-        Ok(())
+/// Cuts the title to `width` characters, the last being `…`.
+fn truncate_title(title: &str, width: Option<usize>) -> String {
+    match width {
+        Some(width) if title.chars().count() > width => {
+            let mut truncated = title.chars().take(width - 1).collect::<String>();
+            truncated.push('…');
+            truncated
+        }
+        _ => title.to_string(),
     }
 }
 
-impl Bean for IssueLabelLink {
-    fn insert(&self, database: &SqlBase) -> anyhow::Result<()> {
-        database
-            .run(format!(
-                "insert overwrite issue_labels values({}, {});",
-                self.issue_id, self.label_id
-            ))?
-            .done()?;
+/// Strips ANSI escapes, backticks and markdown emphasis from a title and collapses its whitespace.
+fn plain_title(title: &str) -> String {
+    let mut chars = Vec::with_capacity(title.len());
+    let mut iter = title.chars().peekable();
+    while let Some(c) = iter.next() {
+        // e.g. `\x1b[1;31m`
+        if c == '\x1b' {
+            if iter.next_if_eq(&'[').is_some() {
+                while iter.next().is_some_and(|c| !c.is_ascii_alphabetic()) {}
+            }
+            continue;
+        }
+        if c != '`' {
+            chars.push(c);
+        }
+    }
 
-        Ok(())
+    let mut plain = String::with_capacity(chars.len());
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if !matches!(c, '*' | '_' | '~') {
+            plain.push(c);
+            i += 1;
+            continue;
+        }
+        let end = chars[i..]
+            .iter()
+            .position(|next| *next != c)
+            .map_or(chars.len(), |len| i + len);
+        let word_before = i > 0 && chars[i - 1].is_alphanumeric();
+        let word_after = end < chars.len() && chars[end].is_alphanumeric();
+        // Emphasis markers hug a word on exactly one side, unlike `snake_case` or `a * b`
+        let is_marker = word_before != word_after && (c != '~' || end - i > 1);
+        if !is_marker {
+            plain.extend(&chars[i..end]);
+        }
+        i = end;
     }
 
-    fn delete(&self, database: &SqlBase) -> anyhow::Result<()> {
-        database
-            .run(format!(
-                "delete from issue_labels where issue_id = {} and label_id = {};",
-                self.issue_id, self.label_id
-            ))?
-            .done()?;
+    plain.split_whitespace().collect::<Vec<_>>().join(" ")
+}
 
-        Ok(())
+fn print_issues(issues: &[Issue]) {
+    let mut table = Table::new();
+
+    table.add_row(row![
+        "ID",
+        "Number",
+        "Repository",
+        "Title",
+        "State",
+        "User",
+        "Labels",
+        "Created At"
+    ]);
+
+    for issue in issues {
+        let labels = issue
+            .labels
+            .iter()
+            .map(|label| label.name.clone())
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        table.add_row(row![
+            issue.id,
+            issue.number,
+            issue.repo_name,
+            issue.title,
+            issue.state_with_lock(),
+            issue.user.login,
+            labels,
+            issue.created_at
+        ]);
     }
+
+    table.printstd();
+}
+
+/// Spawns `PAGER` (`less` by default) when stdout is a terminal, `None` when there is no pager to spawn.
+fn spawn_pager(no_pager: bool) -> Option<process::Child> {
+    let pager = std::env::var("PAGER").unwrap_or_else(|_| "less".to_string());
+    let mut words = pager.split_whitespace();
+    let program = match words.next() {
+        Some(program) if !no_pager && program != "cat" && std::io::stdout().is_terminal() => {
+            program
+        }
+        _ => return None,
+    };
+
+    process::Command::new(program)
+        .args(words)
+        // Like git: quit when the output fits on one screen and keep the colors
+        .env(
+            "LESS",
+            std::env::var("LESS").unwrap_or_else(|_| "FRX".to_string()),
+        )
+        .stdin(Stdio::piped())
+        .spawn()
+        .ok()
 }
 
-impl Bean for Repo {
-    fn insert(&self, database: &SqlBase) -> anyhow::Result<()> {
-        database
-            .run(format!(
-                "insert overwrite repos values('{}', '{}');",
-                self.owner_name, self.name
-            ))?
-            .done()?;
-
-        Ok(())
-    }
-
-    fn delete(&self, database: &SqlBase) -> anyhow::Result<()> {
-        database
-            .run(format!(
-                "delete from repos where owner_name = '{}' and name = '{}';",
-                self.owner_name, self.name
-            ))?
-            .done()?;
-        database
-            .run(format!(
-                "delete from issues where repo_name = '{}';",
-                self.full_name()
-            ))?
-            .done()?;
-
-        Ok(())
+/// Hands the output to the pager and waits for it to quit.
+fn write_to_pager(
+    mut child: process::Child,
+    write: impl FnOnce(&mut process::ChildStdin) -> io::Result<()>,
+) -> anyhow::Result<()> {
+    if let Some(mut stdin) = child.stdin.take() {
+        // The pager closing early is not an error, e.g. quitting `less` after the first screen
+        match write(&mut stdin) {
+            Err(err) if err.kind() != io::ErrorKind::BrokenPipe => return Err(err.into()),
+            _ => {}
+        }
     }
+    child.wait()?;
+
+    Ok(())
+}
+
+/// Pipes the table through the pager, printing it directly when there is no pager to spawn.
+fn print_paged(table: &Table, no_pager: bool) -> anyhow::Result<()> {
+    let Some(child) = spawn_pager(no_pager) else {
+        table.printstd();
+        return Ok(());
+    };
+
+    write_to_pager(child, |stdin| table.print(stdin).map(|_| ()))
 }
 
-impl Bean for User {
-    fn insert(&self, database: &SqlBase) -> anyhow::Result<()> {
-        database
-            .run(format!(
-                "insert overwrite users values({}, '{}');",
-                self.id, self.login
-            ))?
-            .done()?;
+fn print_text_paged(text: &str, no_pager: bool) -> anyhow::Result<()> {
+    let Some(child) = spawn_pager(no_pager) else {
+        print!("{}", text);
+        return Ok(());
+    };
+
+    write_to_pager(child, |stdin| stdin.write_all(text.as_bytes()))
+}
 
-        Ok(())
+/// Formats markdown for the terminal: bold headings, bullets for list items, a bar before quotes,
+/// indented code blocks, `**bold**` and `` `code` ``. Anything else stays as written.
+fn render_markdown(markdown: &str) -> String {
+    let mut rendered = String::with_capacity(markdown.len());
+    let mut in_code_block = false;
+
+    for line in markdown.lines() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") {
+            in_code_block = !in_code_block;
+            continue;
+        }
+        if in_code_block {
+            rendered.push_str(&format!("    \x1b[2m{}\x1b[0m\n", line));
+            continue;
+        }
+        let heading = trimmed.trim_start_matches('#');
+        let level = trimmed.len() - heading.len();
+        if (1..=6).contains(&level) && (heading.is_empty() || heading.starts_with(' ')) {
+            rendered.push_str(&format!("\x1b[1;4m{}\x1b[0m\n", heading.trim()));
+        } else if let Some(item) = ["- ", "* ", "+ "]
+            .iter()
+            .find_map(|marker| trimmed.strip_prefix(marker))
+        {
+            let indent = &line[..line.len() - trimmed.len()];
+            rendered.push_str(&format!("{}• {}\n", indent, render_inline(item)));
+        } else if let Some(quote) = trimmed.strip_prefix('>') {
+            rendered.push_str(&format!(
+                "\x1b[2m│\x1b[0m {}\n",
+                render_inline(quote.trim_start())
+            ));
+        } else {
+            rendered.push_str(&render_inline(line));
+            rendered.push('\n');
+        }
     }
 
-    fn delete(&self, database: &SqlBase) -> anyhow::Result<()> {
-        database
-            .run(format!("delete from users where id = {};", self.id))?
-            .done()?;
+    rendered
+}
 
-        Ok(())
+/// `**bold**` and `` `code` `` of a line, an unclosed marker is kept as is.
+fn render_inline(line: &str) -> String {
+    let mut rendered = String::with_capacity(line.len());
+    let mut rest = line;
+
+    loop {
+        let bold = rest.find("**");
+        let code = rest.find('`');
+        let (start, marker, style) = match (bold, code) {
+            (Some(bold), Some(code)) if code < bold => (code, "`", "\x1b[36m"),
+            (Some(bold), _) => (bold, "**", "\x1b[1m"),
+            (None, Some(code)) => (code, "`", "\x1b[36m"),
+            (None, None) => break,
+        };
+        let inner = &rest[start + marker.len()..];
+        let Some(end) = inner.find(marker) else {
+            break;
+        };
+        rendered.push_str(&rest[..start]);
+        rendered.push_str(&format!("{}{}\x1b[0m", style, &inner[..end]));
+        rest = &inner[end + marker.len()..];
     }
+    rendered.push_str(rest);
+
+    rendered
 }
 
-impl Bean for Issue {
-    fn insert(&self, database: &SqlBase) -> anyhow::Result<()> {
-        database
-            .run(format!(
-                "insert overwrite issues values({}, {}, '{}', '{}', '{}', {}, '{}');",
-                self.id,
-                self.number,
-                escape_sql_string(&self.title),
-                self.state,
-                self.repo_name,
-                self.user.id,
-                self.created_at.format("%Y-%m-%d %H:%M:%S"),
-            ))?
-            .done()?;
-        self.user.insert(database)?;
-        for label in &self.labels {
-            IssueLabelLink {
-                issue_id: self.id,
-                label_id: label.id,
-            }
-            .insert(database)?;
-            label.insert(database)?;
-        }
-
-        Ok(())
-    }
-
-    fn delete(&self, database: &SqlBase) -> anyhow::Result<()> {
-        database
-            .run(format!("delete from issues where id = {};", self.id))?
-            .done()?;
-        database
-            .run(format!(
-                "delete from issue_labels where issue_id = {};",
-                self.id
-            ))?
-            .done()?;
-
-        Ok(())
+fn print_json(value: &impl serde::Serialize, pretty: bool) -> anyhow::Result<()> {
+    if pretty {
+        serde_json::to_writer_pretty(std::io::stdout(), value)?;
+    } else {
+        serde_json::to_writer(std::io::stdout(), value)?;
     }
+    println!();
+
+    Ok(())
 }
 
-impl Issue {
-    fn load_user(&mut self, database: &SqlBase) -> anyhow::Result<()> {
-        let mut iter = database.run(format!("select * from users where id = {}", self.user_id))?;
-        let schema = iter.schema().clone();
-        let tuple = iter
-            .next()
-            .transpose()?
-            .unwrap_or_else(|| panic!("user: {} not found", self.user_id));
-        self.user = User::from((&schema, tuple));
+/// Runs the command with `sh -c`, so the user quotes it as in their shell, writing the
+/// summary to its stdin.
+fn run_hook(command: &str, summary: &serde_json::Value) -> anyhow::Result<()> {
+    let mut child = process::Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::piped())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // A command that does not read its stdin closes the pipe early, which is fine
+        let _ = serde_json::to_writer(&mut stdin, summary);
+    }
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(anyhow!("{}", status));
+    }
+
+    Ok(())
+}
 
-        Ok(())
+/// Issues read from the database and written to the file at a time by `export --jsonl`.
+const EXPORT_BATCH_SIZE: usize = 1000;
+
+/// Writes all issues as JSON Lines, lowest id first. After every batch the last id and the file
+/// length are stored in `<path>.progress`, a resumed export cuts the file back to that length,
+/// so a batch half written when interrupted is neither lost nor duplicated.
+/// Returns the number of issues written by this run.
+fn export_jsonl(
+    client: &Client,
+    path: &Path,
+    resume: bool,
+    pseudonyms: Option<&HashMap<u64, String>>,
+) -> anyhow::Result<usize> {
+    let mut progress_path = path.as_os_str().to_owned();
+    progress_path.push(".progress");
+    let progress_path = PathBuf::from(progress_path);
+
+    let (mut last_id, mut length) = (0, 0);
+    if resume {
+        let progress = fs::read_to_string(&progress_path)
+            .map_err(|err| anyhow!("no export to resume, {}: {}", progress_path.display(), err))?;
+        let (id, offset) = progress
+            .trim()
+            .split_once(' ')
+            .ok_or_else(|| anyhow!("malformed progress file {}", progress_path.display()))?;
+        (last_id, length) = (id.parse()?, offset.parse()?);
     }
+    let mut file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(!resume)
+        .open(path)?;
+    file.set_len(length)?;
+    file.seek(SeekFrom::End(0))?;
+    let mut file = BufWriter::new(file);
+    let mut count = 0;
+
+    loop {
+        let issues = client.issues_after_id(last_id, EXPORT_BATCH_SIZE)?;
+        let Some(last) = issues.last() else {
+            break;
+        };
+        last_id = last.id;
+        for mut issue in issues {
+            issue.load_user(client.database())?;
+            issue.load_labels(client.database())?;
+            issue.load_note(client.database())?;
+            issue.load_lock(client.database())?;
+            issue.load_first_responder(client.database())?;
+            if let Some(pseudonyms) = pseudonyms {
+                issue.anonymize(pseudonyms);
+            }
+            serde_json::to_writer(&mut file, &issue.to_json(&ISSUE_FIELDS))?;
+            file.write_all(b"\n")?;
+            count += 1;
+        }
+        file.flush()?;
+        length = file.get_ref().stream_position()?;
+        fs::write(&progress_path, format!("{} {}", last_id, length))?;
+    }
+    fs::remove_file(&progress_path).or_else(|err| match err.kind() {
+        io::ErrorKind::NotFound => Ok(()),
+        _ => Err(err),
+    })?;
 
-    fn load_labels(&mut self, database: &SqlBase) -> anyhow::Result<()> {
-        let iter = database.run(format!("SELECT l.* FROM labels l INNER JOIN issue_labels il ON l.id = il.label_id WHERE il.issue_id = {};", self.id))?;
-        let schema = iter.schema().clone();
+    Ok(count)
+}
 
-        self.labels.clear();
-        for tuple in iter {
-            self.labels.push(Label::from((&schema, tuple?)));
+/// `owner__name.json`, with anything but ASCII alphanumerics, `-`, `_` and `.` replaced by `_`
+/// and a leading `.` escaped so no name can leave the directory or hide in it.
+fn export_file_name(repo: &Repo) -> String {
+    let sanitize = |part: &str| {
+        let part = part
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.') {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect::<String>();
+        match part.strip_prefix('.') {
+            Some(rest) => format!("_{}", rest),
+            None => part,
         }
+    };
+
+    format!(
+        "{}__{}.json",
+        sanitize(&repo.owner_name),
+        sanitize(&repo.name)
+    )
+}
+
+/// Prints the value in the structured `format`, which is json unless it is yaml.
+fn print_structured(
+    value: &impl serde::Serialize,
+    format: &Format,
+    pretty: bool,
+) -> anyhow::Result<()> {
+    match format {
+        Format::Yaml => serde_yaml::to_writer(std::io::stdout(), value)?,
+        _ => print_json(value, pretty)?,
+    }
+
+    Ok(())
+}
 
-        Ok(())
+fn print_groups(groups: Vec<(String, u64)>, format: &Format, pretty: bool) -> anyhow::Result<()> {
+    match format {
+        Format::Table => {
+            let mut table = Table::new();
+
+            table.add_row(row!["Group", "Count"]);
+            for (group, count) in groups {
+                table.add_row(row![group, count]);
+            }
+            table.printstd();
+        }
+        Format::Json | Format::Yaml => {
+            let objects = groups
+                .into_iter()
+                .map(|(group, count)| serde_json::json!({ "group": group, "count": count }))
+                .collect::<Vec<_>>();
+            print_structured(&objects, format, pretty)?;
+        }
+        Format::Count => {
+            println!("{}", groups.iter().map(|(_, count)| count).sum::<u64>());
+        }
     }
+
+    Ok(())
+}
+
+/// The xterm values of the 16 ANSI foreground colors.
+const ANSI_COLORS: [(u8, [u8; 3]); 16] = [
+    (30, [0, 0, 0]),
+    (31, [205, 0, 0]),
+    (32, [0, 205, 0]),
+    (33, [205, 205, 0]),
+    (34, [0, 0, 238]),
+    (35, [205, 0, 205]),
+    (36, [0, 205, 205]),
+    (37, [229, 229, 229]),
+    (90, [127, 127, 127]),
+    (91, [255, 0, 0]),
+    (92, [0, 255, 0]),
+    (93, [255, 255, 0]),
+    (94, [92, 92, 255]),
+    (95, [255, 0, 255]),
+    (96, [0, 255, 255]),
+    (97, [255, 255, 255]),
+];
+
+/// Nearest ANSI foreground color of a GitHub label color such as `d73a4a`,
+/// the default foreground `39` for malformed values.
+fn ansi_color(hex: &str) -> u8 {
+    let rgb = match hex.len() {
+        6 => (0..3)
+            .map(|i| u8::from_str_radix(hex.get(i * 2..i * 2 + 2)?, 16).ok())
+            .collect::<Option<Vec<_>>>(),
+        _ => None,
+    };
+    let Some(rgb) = rgb else {
+        return 39;
+    };
+
+    ANSI_COLORS
+        .iter()
+        .min_by_key(|(_, color)| {
+            color
+                .iter()
+                .zip(&rgb)
+                .map(|(a, b)| (*a as i32 - *b as i32).pow(2))
+                .sum::<i32>()
+        })
+        .map(|(code, _)| *code)
+        .unwrap()
 }
 
-fn escape_sql_string(input: &str) -> String {
-    input.replace("'", "''")
+/// Every label is wrapped in exactly one color escape, which keeps the table aligned.
+fn colored_label(label: &Label) -> String {
+    let code = label.color.as_deref().map_or(39, ansi_color);
+
+    format!("\x1b[{}m{}\x1b[0m", code, label.name)
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     let cli = Cli::parse();
-    let dir_path = dirs::home_dir()
-        .expect("Your system does not have a Config directory!")
-        .join("issue-hunter");
-    let database = DataBaseBuilder::path(dir_path).build()?;
-    let client = Client {
-        client: Default::default(),
-        database,
+    if let Command::Completions(args) = &cli.command {
+        let mut command = Cli::command();
+        let name = command.get_name().to_string();
+        clap_complete::generate(args.shell, &mut command, name, &mut std::io::stdout());
+
+        return Ok(());
+    }
+    let dir_path = config::data_dir()?;
+    let config_dir = config::config_dir()?;
+    if let Some(legacy_dir) = config::migrate_legacy_dir(&dir_path, &config_dir)? {
+        eprintln!("Moved {} to {}", legacy_dir.display(), dir_path.display());
+    }
+    let config = Config::load(&config_dir)?;
+    // Held until main returns, the OS drops it when the process dies
+    let _lock = match &cli.command {
+        Command::Update(_) => Some(DirLock::exclusive(&dir_path)?),
+        Command::Fetch(args) | Command::FetchPrs(args) if args.respect_lock => {
+            Some(DirLock::shared(&dir_path)?)
+        }
+        _ => None,
     };
-    client.create_table()?;
+    let mut client =
+        Client::with_storage(dir_path, std::env::var("GITHUB_TOKEN").ok(), cli.storage)?;
+    if let Command::Update(args) = &cli.command {
+        client.limit_concurrency(args.concurrency.get());
+        client.retry_attempts(args.attempts.get());
+    }
 
     match &cli.command {
         Command::Update(args) => {
-            client.update_issues(args).await?;
+            if args.prune_missing
+                && !confirm(cli.yes, "Delete stored issues GitHub no longer returns?")?
+            {
+                return Ok(());
+            }
+            let synced = client.update_issues(args).await?;
+
+            if args.stats {
+                let stats = client.request_stats();
+                println!(
+                    "{} requests, {} not modified, {:.1} KiB received",
+                    stats.requests,
+                    stats.not_modified,
+                    stats.bytes as f64 / 1024.0
+                );
+            }
+
+            if let Some(command) = args.on_complete.as_ref().or(config.on_complete.as_ref()) {
+                let summary = serde_json::json!({
+                    "issues": synced.iter().map(|(_, count)| count).sum::<usize>(),
+                    "repos": synced
+                        .iter()
+                        .map(|(repo_name, count)| {
+                            serde_json::json!({ "repo_name": repo_name, "issues": count })
+                        })
+                        .collect::<Vec<_>>(),
+                });
+                // The sync is stored already, a failing hook must not turn it into an error
+                if let Err(err) = run_hook(command, &summary) {
+                    eprintln!("on-complete command failed: {}", err);
+                }
+            }
         }
         Command::AddRepo(repo) => {
-            client.add_repo(repo)?;
+            client.add_repo(repo).await?;
+        }
+        Command::AddRepos(args) => {
+            let (added, malformed) = client.add_repos(args).await?;
+
+            println!("Added {} repos", added);
+            for (line_number, line) in malformed {
+                println!("Malformed line {}: {}", line_number, line);
+            }
+        }
+        Command::ImportStarred(args) => {
+            let (added, skipped) = client.import_starred(args).await?;
+
+            println!("Added {} repos, {} already tracked", added, skipped);
+        }
+        Command::ImportJson(args) => {
+            let count = client.import_json(args)?;
+
+            println!("Imported {} issues", count);
+        }
+        Command::SyncLabels => {
+            let count = client.sync_labels().await?;
+
+            println!("Synced {} labels", count);
+        }
+        Command::Note(args) => match client.note(args)? {
+            Some(note) => println!("{}#{}: {}", args.repo_name, args.number, note),
+            None => println!("Cleared the note of {}#{}", args.repo_name, args.number),
+        },
+        Command::Export(args) => {
+            let pseudonyms = if args.anonymize {
+                Some(client.user_pseudonyms()?)
+            } else {
+                None
+            };
+            if let Some(path) = &args.jsonl {
+                let count = export_jsonl(&client, path, args.resume, pseudonyms.as_ref())?;
+                println!("Wrote {} issues to {}", count, path.display());
+                return Ok(());
+            }
+            if let Some(output_dir) = &args.output_dir {
+                fs::create_dir_all(output_dir)?;
+            }
+            let mut objects = Vec::new();
+
+            for repo in client.repos()? {
+                let repo = repo?;
+                let mut repo_objects = Vec::new();
+                for mut issue in client.repo_issues(&repo.full_name())? {
+                    issue.load_user(client.database())?;
+                    issue.load_labels(client.database())?;
+                    issue.load_note(client.database())?;
+                    issue.load_lock(client.database())?;
+                    issue.load_first_responder(client.database())?;
+                    if let Some(pseudonyms) = &pseudonyms {
+                        issue.anonymize(pseudonyms);
+                    }
+                    repo_objects.push(issue.to_json(&ISSUE_FIELDS));
+                }
+                let Some(output_dir) = &args.output_dir else {
+                    objects.append(&mut repo_objects);
+                    continue;
+                };
+                let path = output_dir.join(export_file_name(&repo));
+                let file = BufWriter::new(File::create(&path)?);
+                if args.pretty {
+                    serde_json::to_writer_pretty(file, &repo_objects)?;
+                } else {
+                    serde_json::to_writer(file, &repo_objects)?;
+                }
+                println!("Wrote {} issues to {}", repo_objects.len(), path.display());
+            }
+            if args.output_dir.is_none() {
+                print_json(&objects, args.pretty)?;
+            }
+        }
+        Command::RefreshStates(args) => {
+            for (repo_name, changed) in client.refresh_states(args).await? {
+                println!("{}: {} states changed", repo_name, changed);
+            }
+        }
+        Command::Show(args) => {
+            let mut issue = client.issue(&args.repo_name, args.number)?.ok_or_else(|| {
+                Error::NotFound(format!("Issue: '{}#{}'", args.repo_name, args.number))
+            })?;
+            issue.load_user(client.database())?;
+            issue.load_labels(client.database())?;
+            issue.load_note(client.database())?;
+            issue.load_lock(client.database())?;
+            issue.load_first_responder(client.database())?;
+
+            let mut text = format!(
+                "{} {}#{}\n{}, opened by {} on {}\n",
+                issue.title,
+                issue.repo_name,
+                issue.number,
+                issue.state_with_lock(),
+                issue.user.login,
+                issue.created_at
+            );
+            if let Some(first_responder) = &issue.first_responder {
+                text.push_str(&format!("First response by {}\n", first_responder.login));
+            }
+            if !issue.labels.is_empty() {
+                let labels = issue
+                    .labels
+                    .iter()
+                    .map(|label| label.name.as_str())
+                    .collect::<Vec<_>>();
+                text.push_str(&format!("Labels: {}\n", labels.join(", ")));
+            }
+            if let Some(note) = &issue.note {
+                text.push_str(&format!("Note: {}\n", note));
+            }
+            text.push('\n');
+            match issue.body.as_deref().filter(|body| !body.trim().is_empty()) {
+                Some(body) if args.raw || !io::stdout().is_terminal() => {
+                    text.push_str(body);
+                    text.push('\n');
+                }
+                Some(body) => text.push_str(&render_markdown(body)),
+                None => text.push_str("No description provided.\n"),
+            }
+            print_text_paged(&text, args.no_pager)?;
+        }
+        Command::Refresh(args) => {
+            if client.refresh(args).await? {
+                println!("Refreshed {}#{}", args.repo_name, args.number);
+            } else {
+                println!(
+                    "{}#{} no longer exists on GitHub",
+                    args.repo_name, args.number
+                );
+                let remove = confirm(cli.yes, "Remove the local copy?")?;
+                if remove && client.remove_issue(&args.repo_name, args.number)? {
+                    println!("Removed {}#{}", args.repo_name, args.number);
+                }
+            }
+        }
+        Command::RemoveIssues(args) => {
+            let ids = client.issues_with_label(&args.label)?;
+            if ids.is_empty() {
+                println!("No issues labeled '{}'", args.label);
+                return Ok(());
+            }
+            let prompt = format!("Remove {} issues labeled '{}'?", ids.len(), args.label);
+            if confirm(cli.yes, prompt)? {
+                client.remove_issues(&ids)?;
+                println!("Removed {} issues", ids.len());
+            }
+        }
+        Command::EditRepo(args) => {
+            client.edit_repo(args)?;
+        }
+        Command::ArchiveRepo(repo) | Command::UnarchiveRepo(repo) => {
+            let archived = matches!(&cli.command, Command::ArchiveRepo(_));
+            if !client.set_archived(repo, archived)? {
+                return Err(Error::NotFound(format!("Repo: '{}'", repo.full_name())).into());
+            }
+        }
+        Command::SetTokenEnv(args) => {
+            let repo = args.repo_name.parse::<Repo>()?;
+            if !client.set_token_env(&repo, args.token_env.as_deref())? {
+                return Err(Error::NotFound(format!("Repo: '{}'", repo.full_name())).into());
+            }
         }
         Command::RemoveRepo(repo) => {
-            client.remove_repo(repo)?;
+            let (issues, prs) = client.repo_counts(repo)?;
+            let prompt = format!(
+                "Remove repo {} with its {} issues and {} pull requests?",
+                repo.full_name(),
+                issues,
+                prs
+            );
+            if confirm(cli.yes, prompt)? {
+                client.remove_repo(repo)?;
+            }
         }
         Command::Fetch(args) => {
+            let mut args = with_config(&config, args)?;
+            if args.interactive {
+                prompt_filters(&mut args)?;
+            }
+            let args = &args;
+            if args.explain {
+                println!("{}", client.explain("issues", args)?);
+                return Ok(());
+            }
+            if let Some(group_by) = args.group_by {
+                print_groups(
+                    client.group_issues(args, group_by)?,
+                    &args.format,
+                    args.pretty,
+                )?;
+                return Ok(());
+            }
+            if let Format::Count = args.format {
+                println!("{}", client.count_issues(args)?);
+                return Ok(());
+            }
+            let mut table = Table::new();
+
+            let fields = match args.format {
+                _ if args.fields.is_empty() => ISSUE_FIELDS.to_vec(),
+                Format::Json | Format::Yaml => parse_fields(&args.fields)?,
+                _ => return Err(anyhow!("--fields requires --format json or yaml")),
+            };
+            if args.with_meta && !matches!(args.format, Format::Json | Format::Yaml) {
+                return Err(anyhow!("--with-meta requires --format json or yaml"));
+            }
+            let mut objects = Vec::new();
+            let mut rows = Vec::new();
+            let mut cursor = None;
+
+            for issue in client.fetch_issues(args)? {
+                let mut issue = issue?;
+                cursor = Some((issue.created_at, issue.id));
+                if args.plain_titles {
+                    issue.title = plain_title(&issue.title);
+                }
+                if !args.no_user {
+                    issue.load_user(client.database())?;
+                }
+                if !args.no_labels {
+                    issue.load_labels(client.database())?;
+                }
+                issue.load_note(client.database())?;
+                issue.load_lock(client.database())?;
+                issue.load_first_responder(client.database())?;
+                if let Format::Json | Format::Yaml = args.format {
+                    let mut object = issue.to_json(&fields);
+                    replace_nulls(&mut object, args.null_as.as_deref());
+                    objects.push(object);
+                    continue;
+                }
+                rows.push(issue);
+            }
+
+            if args.tree {
+                rows = nest_issues(rows);
+            }
+            // Only pages with a local note get the column
+            let with_notes = rows.iter().any(|issue| issue.note.is_some());
+            let with_first_responders = rows.iter().any(|issue| issue.first_responder.is_some());
+            let mut titles = row![
+                "ID",
+                "Number",
+                "Repository",
+                "Title",
+                "State",
+                "User",
+                "Labels",
+                "Created At"
+            ];
+            if with_first_responders {
+                titles.add_cell(Cell::new(&"First Responder".to_string()));
+            }
+            if with_notes {
+                titles.add_cell(Cell::new(&"Notes".to_string()));
+            }
+            table.add_row(titles);
+            let title_width = title_width(args);
+            // prettytable counts escape sequences as width, so every Labels cell gets as many of them
+            let max_labels = rows.iter().map(|issue| issue.labels.len()).max();
+            for issue in rows {
+                let mut labels = if args.label_summary {
+                    label_summary(issue.labels.len())
+                } else {
+                    issue
+                        .labels
+                        .iter()
+                        .map(|label| {
+                            if args.color {
+                                colored_label(label)
+                            } else {
+                                label.name.clone()
+                            }
+                        })
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                };
+                if args.color {
+                    for _ in issue.labels.len()..max_labels.unwrap_or(0) {
+                        labels.push_str("\x1b[39m\x1b[0m");
+                    }
+                }
+
+                let null_as = args.null_as.as_deref();
+                let mut row = row![
+                    issue.id,
+                    issue.number,
+                    issue.repo_name,
+                    truncate_title(&issue.title, title_width),
+                    issue.state_with_lock(),
+                    or_null(issue.user.login, null_as),
+                    or_null(labels, null_as),
+                    issue.created_at
+                ];
+                if with_first_responders {
+                    row.add_cell(Cell::new(&or_null(
+                        issue
+                            .first_responder
+                            .map(|first_responder| first_responder.login)
+                            .unwrap_or_default(),
+                        null_as,
+                    )));
+                }
+                if with_notes {
+                    row.add_cell(Cell::new(&or_null(issue.note.unwrap_or_default(), null_as)));
+                }
+                table.add_row(row);
+            }
+
+            if args.with_meta {
+                let envelope = serde_json::json!({
+                    "page": args.page,
+                    "page_size": args.page_size(),
+                    "total": client.count_issues(args)?,
+                    "issues": objects,
+                });
+                print_structured(&envelope, &args.format, args.pretty)?;
+            } else if let Format::Json | Format::Yaml = args.format {
+                print_structured(&objects, &args.format, args.pretty)?;
+            } else {
+                print_paged(&table, args.no_pager)?;
+            }
+            if let (Some(_), Some((created_at, id))) = (args.after_created, cursor) {
+                eprintln!(
+                    "Next page: --after-created {} --after-id {}",
+                    created_at.to_rfc3339(),
+                    id
+                );
+            }
+        }
+        Command::FetchPrs(args) => {
+            let mut args = with_config(&config, args)?;
+            if args.interactive {
+                prompt_filters(&mut args)?;
+            }
+            let args = &args;
+            if args.explain {
+                println!("{}", client.explain("prs", args)?);
+                return Ok(());
+            }
+            if let Some(group_by) = args.group_by {
+                print_groups(client.group_prs(args, group_by)?, &args.format, args.pretty)?;
+                return Ok(());
+            }
+            if let Format::Count = args.format {
+                println!("{}", client.count_prs(args)?);
+                return Ok(());
+            }
+            // Pull requests are only listed as a table so far
+            let unsupported = [
+                (
+                    matches!(args.format, Format::Json | Format::Yaml),
+                    "--format json or yaml without --group-by",
+                ),
+                (!args.fields.is_empty(), "--fields"),
+                (args.with_meta, "--with-meta"),
+                (args.tree, "--tree"),
+                (args.color, "--color"),
+            ];
+            if let Some((_, flag)) = unsupported.iter().find(|(given, _)| *given) {
+                return Err(anyhow!("{} is not supported by fetch-prs", flag));
+            }
             let mut table = Table::new();
 
             table.add_row(row![
@@ -331,64 +1079,322 @@ async fn main() -> anyhow::Result<()> {
                 "Repository",
                 "Title",
                 "State",
+                "Merged",
+                "Head",
+                "Base",
                 "User",
                 "Labels",
                 "Created At"
             ]);
 
-            for issue in client.fetch_issues(args)? {
-                let mut issue = issue?;
-                issue.load_user(&client.database)?;
-                issue.load_labels(&client.database)?;
+            let title_width = title_width(args);
 
-                let labels = issue
-                    .labels
-                    .iter()
-                    .map(|label| label.name.clone())
-                    .collect::<Vec<_>>()
-                    .join(", ");
+            for pr in client.fetch_prs(args)? {
+                let mut pr = pr?;
+                if args.plain_titles {
+                    pr.issue.title = plain_title(&pr.issue.title);
+                }
+                if !args.no_user {
+                    pr.issue.load_user(client.database())?;
+                }
+                if !args.no_labels {
+                    pr.issue.load_labels(client.database())?;
+                }
 
+                let labels = if args.label_summary {
+                    label_summary(pr.issue.labels.len())
+                } else {
+                    pr.issue
+                        .labels
+                        .iter()
+                        .map(|label| label.name.clone())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                };
+
+                let null_as = args.null_as.as_deref();
                 table.add_row(row![
-                    issue.id,
-                    issue.number,
-                    issue.repo_name,
-                    issue.title,
-                    issue.state,
-                    issue.user.login,
-                    labels,
-                    issue.created_at
+                    pr.issue.id,
+                    pr.issue.number,
+                    pr.issue.repo_name,
+                    truncate_title(&pr.issue.title, title_width),
+                    pr.issue.state,
+                    pr.merged,
+                    or_null(pr.head.unwrap_or_default(), null_as),
+                    or_null(pr.base.unwrap_or_default(), null_as),
+                    or_null(pr.issue.user.login, null_as),
+                    or_null(labels, null_as),
+                    pr.issue.created_at
                 ]);
             }
 
+            print_paged(&table, args.no_pager)?;
+        }
+        Command::Discussions(args) => {
+            let mut args = args.clone();
+            if args.page_num.is_none() {
+                args.page_num = config.default_page_size;
+            }
+            let mut table = Table::new();
+
+            table.add_row(row![
+                "ID",
+                "Number",
+                "Repository",
+                "Title",
+                "Category",
+                "Author",
+                "Created At"
+            ]);
+            for discussion in client.discussions(&args)? {
+                table.add_row(row![
+                    discussion.id,
+                    discussion.number,
+                    discussion.repo_name,
+                    discussion.title,
+                    discussion.category.unwrap_or_default(),
+                    discussion.author.unwrap_or_default(),
+                    discussion.created_at
+                ]);
+            }
             table.printstd();
         }
-        Command::Repos => {
+        Command::Mine => {
+            print_issues(&client.mine().await?);
+        }
+        Command::SearchGithub(args) => {
+            print_issues(&client.search_github(args).await?);
+        }
+        Command::Repos(args) => {
+            let mut repos = client.repos()?.collect::<Result<Vec<_>, _>>()?;
+            for repo in repos.iter_mut() {
+                repo.load_topics(client.database())?;
+            }
+
+            match args.format {
+                Format::Table => {
+                    let mut table = Table::new();
+
+                    table.add_row(row![
+                        "Owner",
+                        "Name",
+                        "Default Branch",
+                        "Url",
+                        "Archived",
+                        "Topics"
+                    ]);
+                    for repo in repos {
+                        table.add_row(row![
+                            repo.owner_name,
+                            repo.name,
+                            repo.default_branch.as_deref().unwrap_or_default(),
+                            repo.url(),
+                            repo.archived,
+                            repo.topics.join(", ")
+                        ]);
+                    }
+                    table.printstd();
+                }
+                Format::Json | Format::Yaml => {
+                    let objects = repos.iter().map(Repo::to_json).collect::<Vec<_>>();
+                    print_structured(&objects, &args.format, args.pretty)?;
+                }
+                Format::Count => {
+                    println!("{}", repos.len());
+                }
+            }
+        }
+        Command::LastUpdated => {
             let mut table = Table::new();
+            let now = Utc::now();
+
+            table.add_row(row!["Repository", "Last Updated", "Ago"]);
+
+            let mut repos = client.repos()?.collect::<Result<Vec<_>, _>>()?;
+            // Never updated repos are the stalest
+            repos.sort_by_key(|repo| repo.last_updated);
+
+            for repo in repos {
+                match repo.last_updated {
+                    Some(last_updated) => table.add_row(row![
+                        repo.full_name(),
+                        last_updated,
+                        humanize_duration(now - last_updated)
+                    ]),
+                    None => table.add_row(row![repo.full_name(), "never", ""]),
+                };
+            }
 
-            table.add_row(row!["Owner", "Name", "Url"]);
+            table.printstd();
+        }
+        Command::Whoami => {
+            let Some(user) = client.whoami().await? else {
+                println!("unauthenticated (60 req/hr limit), set GITHUB_TOKEN to sign in");
+                return Ok(());
+            };
+            let core = client.rate_limit().await?.resources.core;
 
-            for repo in client.repos()? {
-                let repo = repo?;
+            match user.name {
+                Some(name) => println!("Logged in as {} ({})", user.login, name),
+                None => println!("Logged in as {}", user.login),
+            }
+            if let Some(plan) = user.plan {
+                println!("Plan: {}", plan.name);
+            }
+            println!(
+                "Rate limit: {} req/hr, {} remaining",
+                core.limit, core.remaining
+            );
+        }
+        Command::RateLimit => {
+            let resources = client.rate_limit().await?.resources;
+            let mut table = Table::new();
 
+            table.add_row(row!["Resource", "Remaining", "Limit", "Resets At"]);
+            for (name, rate_limit) in [("core", resources.core), ("search", resources.search)] {
                 table.add_row(row![
-                    repo.owner_name,
-                    repo.name,
-                    format!("https://github.com/{}", repo.full_name())
+                    name,
+                    rate_limit.remaining,
+                    rate_limit.limit,
+                    rate_limit
+                        .reset
+                        .with_timezone(&Local)
+                        .format("%Y-%m-%d %H:%M:%S")
                 ]);
             }
 
             table.printstd();
         }
+        Command::Reprocess => {
+            println!("Reprocessed {} issues", client.reprocess()?);
+        }
+        Command::AgeHistogram(args) => {
+            print_groups(client.age_histogram(args, Utc::now())?, &args.format, false)?;
+        }
+        Command::Timeseries(args) => {
+            let series = client.timeseries(args)?;
+            let bucket_name = |bucket: NaiveDate| match args.bucket {
+                Bucket::Month => bucket.format("%Y-%m").to_string(),
+                Bucket::Day | Bucket::Week => bucket.to_string(),
+            };
+
+            match args.format {
+                SeriesFormat::Table => {
+                    let mut table = Table::new();
+
+                    table.add_row(row!["Bucket", "Count"]);
+                    for (bucket, count) in series {
+                        table.add_row(row![bucket_name(bucket), count]);
+                    }
+                    table.printstd();
+                }
+                SeriesFormat::Json => {
+                    let objects = series
+                        .into_iter()
+                        .map(|(bucket, count)| {
+                            serde_json::json!({ "bucket": bucket_name(bucket), "count": count })
+                        })
+                        .collect::<Vec<_>>();
+                    print_json(&objects, false)?;
+                }
+                SeriesFormat::Csv => {
+                    println!("bucket,count");
+                    for (bucket, count) in series {
+                        println!("{},{}", bucket_name(bucket), count);
+                    }
+                }
+            }
+        }
+        Command::Snapshot => {
+            let count = client.take_snapshot()?;
+
+            println!("Snapshot of {} issues taken", count);
+        }
+        Command::Diff(args) => {
+            let (taken_at, changes) = client.diff_snapshot(args.repo_name.as_deref())?;
+
+            match args.format {
+                Format::Table => {
+                    match taken_at {
+                        Some(taken_at) => println!("Changes since {}", taken_at),
+                        None => println!("No snapshot taken yet, every issue counts as added"),
+                    }
+                    for group in changes.chunk_by(|a, b| a.change == b.change) {
+                        println!();
+                        println!("{} ({})", group[0].change.as_str(), group.len());
+                        for change in group {
+                            print!("  {}#{} {}", change.repo_name, change.number, change.title);
+                            match &change.labels {
+                                Some((before, after)) => {
+                                    println!(" [{}] -> [{}]", before.join(", "), after.join(", "))
+                                }
+                                None => println!(),
+                            }
+                        }
+                    }
+                }
+                Format::Json | Format::Yaml => {
+                    let objects = changes.iter().map(IssueChange::to_json).collect::<Vec<_>>();
+                    print_structured(&objects, &args.format, false)?;
+                }
+                Format::Count => println!("{}", changes.len()),
+            }
+            if args.save {
+                client.take_snapshot()?;
+            }
+        }
+        Command::Check(args) => {
+            let problems = client.check()?;
+            let mut table = Table::new();
+
+            table.add_row(row!["Problem", "Issue", "User / Label"]);
+            for problem in &problems {
+                match problem {
+                    Problem::MissingUser {
+                        table: issues,
+                        issue_id,
+                        user_id,
+                    } => table.add_row(row![
+                        format!("{} row without user", issues),
+                        issue_id,
+                        user_id
+                    ]),
+                    Problem::MissingLabel(link) => table.add_row(row![
+                        "label link without label",
+                        link.issue_id,
+                        link.label_id
+                    ]),
+                    Problem::MissingIssue(link) => table.add_row(row![
+                        "label link without issue",
+                        link.issue_id,
+                        link.label_id
+                    ]),
+                };
+            }
+
+            if problems.is_empty() {
+                println!("No problems found");
+            } else {
+                table.printstd();
+                if args.fix
+                    && confirm(
+                        cli.yes,
+                        "Fix the problems, deleting the broken label links?",
+                    )?
+                {
+                    let fixed = client.fix(&problems).await?;
+                    println!("Fixed {} of {} problems", fixed, problems.len());
+                }
+            }
+        }
+        Command::Completions(_) => {
+            unreachable!("completions are generated before opening the database")
+        }
     }
 
     Ok(())
 }
 
-struct Client {
-    client: reqwest::Client,
-    database: SqlBase,
-}
-
 #[derive(Parser, Debug)]
 #[clap(
     name = "issue-hunter",
@@ -399,227 +1405,221 @@ struct Client {
 struct Cli {
     #[clap(subcommand)]
     command: Command,
+    /// Where the issues are stored, `memory` keeps nothing after the command
+    #[clap(long, global = true, value_enum, default_value = "rocksdb")]
+    storage: StorageKind,
+    /// Skip the confirmation of commands deleting stored data, e.g. in scripts
+    #[clap(long, global = true, action, default_value = "false")]
+    yes: bool,
 }
 
 #[derive(Subcommand, Debug)]
 enum Command {
     Update(UpdateArgs),
     AddRepo(Repo),
+    AddRepos(AddReposArgs),
+    /// Track every repository starred by a GitHub user
+    ImportStarred(ImportStarredArgs),
+    /// Store the issues of a GitHub json file, e.g. saved from `gh api`, without going online
+    ImportJson(ImportJsonArgs),
     RemoveRepo(Repo),
+    /// Delete the stored issues carrying a label, e.g. `duplicate`
+    RemoveIssues(RemoveIssuesArgs),
+    /// Rename a tracked repo together with its issues, e.g. after a typo or a move
+    EditRepo(EditRepoArgs),
+    /// Stop syncing a repo but keep its issues, unlike `remove-repo`
+    ArchiveRepo(Repo),
+    /// Sync an archived repo again
+    UnarchiveRepo(Repo),
+    /// Sync a repo with the token of another environment variable than `GITHUB_TOKEN`,
+    /// e.g. for the repos of a work account
+    SetTokenEnv(SetTokenEnvArgs),
+    /// Print a stored issue or pull request with its body formatted for the terminal
+    Show(ShowArgs),
+    /// Fetch a single issue again, e.g. to pick up an edited body without a full sync
+    Refresh(RefreshArgs),
+    /// Update only the open/closed state of the issues changed since the last sync, lighter than `update`
+    RefreshStates(RefreshStatesArgs),
+    /// Attach a private note to an issue or pull request, kept across syncs
+    Note(NoteArgs),
+    /// Write the stored issues of every repo as json
+    Export(ExportArgs),
     Fetch(FetchArgs),
-    Repos,
+    FetchPrs(FetchArgs),
+    /// List the discussions stored by `update --with-discussions`, newest first
+    Discussions(DiscussionsArgs),
+    /// Store all labels of the tracked repos, also those no issue carries yet
+    SyncLabels,
+    /// Fetch the open issues assigned to you across all repositories, requires GITHUB_TOKEN
+    Mine,
+    /// Store and show the issues matching a GitHub search query
+    SearchGithub(SearchGithubArgs),
+    Repos(ReposArgs),
+    /// List repos by how long ago they were last updated, stalest first
+    LastUpdated,
+    /// Check GITHUB_TOKEN by showing the account it belongs to and its rate limit
+    Whoami,
+    /// Show the remaining GitHub API requests of the core and search resources
+    RateLimit,
+    /// Derive the stored columns again from the json kept by `update --store-raw`
+    Reprocess,
+    /// Count the open issues per age, e.g. `7-30d` for issues created 7 to 30 days ago
+    AgeHistogram(AgeHistogramArgs),
+    /// Count the issues created per day, week or month, e.g. `--format csv` for a plotting tool
+    Timeseries(TimeseriesArgs),
+    /// Remember the state and labels of every stored issue for `diff`
+    Snapshot,
+    /// Report the issues added, closed, reopened or relabeled since the last `snapshot`
+    Diff(DiffArgs),
+    /// Report references to users, labels and issues missing from the database
+    Check(CheckArgs),
+    /// Print the shell completion script to stdout
+    Completions(CompletionsArgs),
 }
 
 #[derive(Parser, Debug)]
-struct UpdateArgs {
-    #[clap(long)]
-    create_after: Option<DateTime<Utc>>,
+struct CompletionsArgs {
+    #[clap(value_enum)]
+    shell: Shell,
 }
 
-#[derive(Parser, Debug)]
-struct FetchArgs {
-    #[clap(long)]
-    repo_name: Option<String>,
-    #[clap(long)]
-    create_after: Option<DateTime<Utc>>,
-    #[clap(long, action, default_value = "false")]
-    today: bool,
-    #[clap(long)]
-    label_name: Option<String>,
-    #[clap(long, default_value = "1")]
-    page: usize,
-    #[clap(long, default_value = "10")]
-    page_num: usize,
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use issue_hunter::bean::Bean;
+    use issue_hunter::model::User;
+
+    #[test]
+    fn plain_title_strips_emphasis_markers() {
+        assert_eq!(
+            plain_title("**Bold** and _italic_ title"),
+            "Bold and italic title"
+        );
+        assert_eq!(plain_title("~~struck~~ through"), "struck through");
+        assert_eq!(plain_title("🚀 **launch**"), "🚀 launch");
+    }
 
-impl Client {
-    fn create_table(&self) -> anyhow::Result<()> {
-        self.database
-            .run(
-                "CREATE TABLE IF NOT EXISTS repos (
-    owner_name VARCHAR(255) NOT NULL,
-    name VARCHAR(255) NOT NULL,
-    PRIMARY KEY (owner_name, name)
-);",
-            )?
-            .done()?;
-        self.database
-            .run(
-                "CREATE TABLE IF NOT EXISTS users (
-    id BIGINT PRIMARY KEY,
-    login VARCHAR(255) NOT NULL
-);",
-            )?
-            .done()?;
-        self.database
-            .run(
-                "CREATE TABLE IF NOT EXISTS labels (
-    id BIGINT PRIMARY KEY,
-    name VARCHAR(255) NOT NULL,
-    description VARCHAR(255)
-);",
-            )?
-            .done()?;
-        self.database
-            .run(
-                "CREATE TABLE IF NOT EXISTS issues (
-    id BIGINT PRIMARY KEY,
-    number BIGINT NOT NULL,
-    title TEXT NOT NULL,
-    state VARCHAR(50) NOT NULL,
-    repo_name VARCHAR(255) NOT NULL,
-    user_id BIGINT NOT NULL,
-    created_at DATETIME NOT NULL
-);",
-            )?
-            .done()?;
-        self.database
-            .run(
-                "CREATE TABLE IF NOT EXISTS issue_labels (
-    issue_id BIGINT,
-    label_id BIGINT,
-    PRIMARY KEY (issue_id, label_id)
-);",
-            )?
-            .done()?;
-
-        Ok(())
-    }
-
-    fn fetch_issues<'a>(
-        &'a self,
-        args: &FetchArgs,
-    ) -> anyhow::Result<impl Iterator<Item = Result<Issue, anyhow::Error>> + 'a> {
-        let mut query = "select * from issues where 1 = 1".to_string();
-
-        if let Some(repo_name) = &args.repo_name {
-            query.push_str(&format!(" and repo_name like '{}'", repo_name));
-        }
-
-        let mut create_after = args
-            .create_after
-            .map(|time| time.format("%Y-%m-%d %H:%M:%S").to_string());
-        if args.today {
-            create_after = Some(Utc::now().date_naive().format("%Y-%m-%d").to_string());
-        }
-        if let Some(create_after) = create_after {
-            query.push_str(&format!(" and created_at > '{}'", create_after));
-        }
-        if let Some(label_name) = &args.label_name {
-            let label_id = self
-                .database
-                .run(format!(
-                    "select id from labels where name = '{}'",
-                    label_name
-                ))?
-                .next()
-                .transpose()?
-                .unwrap_or_else(|| panic!("Label: '{}' not Found", label_name))
-                .values[0]
-                .u64()
-                .unwrap();
-
-            // TODO: Cache issue_ids;
-            let mut issue_ids = Vec::new();
-            for result in self.database.run(format!(
-                "select issue_id from issue_labels where label_id = {}",
-                label_id
-            ))? {
-                issue_ids.push(result?.values[0].u64().unwrap().to_string());
-            }
-            query.push_str(&format!(" and id in ({})", issue_ids.join(", ")));
-        }
-        query.write_str(
-            format!(
-                "order by created_at desc limit {} offset {};",
-                args.page_num,
-                (args.page - 1) * args.page_num
-            )
-            .as_str(),
-        )?;
-        let iter = self.database.run(query)?;
-        let schema = iter.schema().clone();
-        Ok(iter.map(move |result| {
-            result
-                .map(|tuple| Issue::from((&schema, tuple)))
-                .map_err(anyhow::Error::from)
-        }))
-    }
-
-    async fn update_issues(&self, args: &UpdateArgs) -> anyhow::Result<()> {
-        let iter = self.database.run("select * from repos")?;
-        let schema = iter.schema().clone();
-
-        for tuple in iter {
-            let repo = Repo::from((&schema, tuple?));
-
-            let page = 1;
-            let created_after = if let Some(datetime) = args.create_after {
-                datetime.timestamp()
-            } else {
-                let now = Utc::now();
-                let today_midnight = Utc.ymd(now.year(), now.month(), now.day()).and_hms(0, 0, 0);
+    #[test]
+    fn plain_title_keeps_markers_inside_or_between_words() {
+        assert_eq!(
+            plain_title("fix `snake_case` handling"),
+            "fix snake_case handling"
+        );
+        assert_eq!(plain_title("a * b"), "a * b");
+        assert_eq!(plain_title("due in ~1 day"), "due in ~1 day");
+    }
 
-                today_midnight.timestamp()
-            };
-            let mut oldest_created = None;
-            while oldest_created
-                .as_ref()
-                .map(|created| *created > created_after)
-                .unwrap_or(true)
-            {
-                let url = format!(
-                    "https://api.github.com/repos/{}/issues?page={}",
-                    repo.full_name(),
-                    page,
-                );
+    #[test]
+    fn plain_title_strips_ansi_escapes_and_collapses_whitespace() {
+        assert_eq!(plain_title("\x1b[1;31mred\x1b[0m alert"), "red alert");
+        assert_eq!(plain_title("  spaced\tout\n title "), "spaced out title");
+        assert_eq!(plain_title(""), "");
+    }
 
-                let response = self
-                    .client
-                    .get(&url)
-                    .header("User-Agent", "reqwest")
-                    .send()
-                    .await?;
+    #[test]
+    fn truncate_title_counts_emoji_as_single_characters() {
+        assert_eq!(
+            truncate_title("🚀🔥🐛 crash in the 日本語 parser", Some(5)),
+            "🚀🔥🐛 …"
+        );
+        assert_eq!(truncate_title("🚀🔥🐛", Some(3)), "🚀🔥🐛");
+    }
 
-                if !response.status().is_success() {
-                    return Err(anyhow!("Request failed with status: {}", response.status()));
-                }
-                for mut issue in response.json::<Vec<Issue>>().await? {
-                    issue.repo_name = repo.full_name();
-                    issue.insert(&self.database)?;
-
-                    let issue_created_at = issue.created_at.timestamp();
-                    match oldest_created {
-                        None => oldest_created = Some(issue_created_at),
-                        Some(timestamp) => {
-                            oldest_created = Some(min(issue_created_at, timestamp));
-                        }
-                    }
-                }
-            }
-        }
-        Ok(())
+    #[test]
+    fn truncate_title_cuts_long_titles_to_the_width() {
+        let title = "x".repeat(1000);
+
+        let truncated = truncate_title(&title, Some(MIN_TITLE_WIDTH));
+        assert_eq!(truncated.chars().count(), MIN_TITLE_WIDTH);
+        assert!(truncated.ends_with('…'));
+        assert_eq!(truncate_title(&title, None), title);
     }
 
-    fn add_repo(&self, repo: &Repo) -> anyhow::Result<()> {
-        repo.insert(&self.database)?;
+    #[test]
+    fn render_markdown_styles_headings_lists_and_quotes() {
+        assert_eq!(
+            render_markdown("## Steps\n- run `ih`\n  * nested\n> **quoted**"),
+            "\x1b[1;4mSteps\x1b[0m\n\
+             • run \x1b[36mih\x1b[0m\n\
+             \x20 • nested\n\
+             \x1b[2m│\x1b[0m \x1b[1mquoted\x1b[0m\n"
+        );
+        assert_eq!(render_markdown("#hashtag"), "#hashtag\n");
+    }
+
+    #[test]
+    fn render_markdown_dims_code_blocks_verbatim() {
+        assert_eq!(
+            render_markdown("```rust\n# not a heading\n- **not bold**\n```\nafter"),
+            "    \x1b[2m# not a heading\x1b[0m\n    \x1b[2m- **not bold**\x1b[0m\nafter\n"
+        );
+    }
 
-        Ok(())
+    #[test]
+    fn render_inline_keeps_unclosed_markers() {
+        assert_eq!(render_inline("**open and `code"), "**open and `code");
+        assert_eq!(
+            render_inline("`a ** b` then **c**"),
+            "\x1b[36ma ** b\x1b[0m then \x1b[1mc\x1b[0m"
+        );
     }
 
-    fn remove_repo(&self, repo: &Repo) -> anyhow::Result<()> {
-        repo.delete(&self.database)?;
+    fn insert_issue(client: &Client, id: u64) {
+        Issue {
+            id,
+            number: id,
+            title: format!("issue {}", id),
+            state: "open".to_string(),
+            repo_name: "a/b".to_string(),
+            user: User {
+                id: 1,
+                login: "octocat".to_string(),
+            },
+            ..Default::default()
+        }
+        .insert(client.database())
+        .unwrap();
+    }
 
-        Ok(())
+    fn exported_ids(path: &Path) -> Vec<u64> {
+        fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(|line| {
+                serde_json::from_str::<serde_json::Value>(line).unwrap()["id"]
+                    .as_u64()
+                    .unwrap()
+            })
+            .collect()
     }
 
-    fn repos(&self) -> anyhow::Result<impl Iterator<Item = Result<Repo, anyhow::Error>> + use<'_>> {
-        let iter = self.database.run("select * from repos;")?;
-        let schema = iter.schema().clone();
-        Ok(iter.map(move |result| {
-            result
-                .map(|tuple| Repo::from((&schema, tuple)))
-                .map_err(anyhow::Error::from)
-        }))
+    #[test]
+    fn resumed_export_continues_after_the_recorded_id() {
+        let client = Client::with_storage("", None, StorageKind::Memory).unwrap();
+        let path = std::env::temp_dir().join(format!("ih-export-{}.jsonl", process::id()));
+        let progress_path = path.with_extension("jsonl.progress");
+        for id in [1, 2] {
+            insert_issue(&client, id);
+        }
+        assert_eq!(export_jsonl(&client, &path, false, None).unwrap(), 2);
+        assert!(!progress_path.exists());
+
+        // An export interrupted after the batch up to issue 2, halfway through writing the next
+        let length = fs::metadata(&path).unwrap().len();
+        fs::write(&progress_path, format!("2 {}", length)).unwrap();
+        fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(br#"{"id":3,"tit"#)
+            .unwrap();
+        for id in [3, 4] {
+            insert_issue(&client, id);
+        }
+
+        assert_eq!(export_jsonl(&client, &path, true, None).unwrap(), 2);
+        assert_eq!(exported_ids(&path), [1, 2, 3, 4]);
+        assert!(!progress_path.exists());
+        fs::remove_file(&path).unwrap();
     }
 }
@@ -0,0 +1,1030 @@
+use crate::bean::Bean;
+use crate::error::Error;
+use crate::{escape_sql_string, SqlBase};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use clap::Parser;
+use kite_sql::db::ResultIter;
+use kite_sql::implement_from_tuple;
+use kite_sql::types::value::DataValue;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::str::FromStr;
+
+#[derive(Deserialize, Debug, Default)]
+pub struct Issue {
+    pub id: u64,
+    pub number: u64,
+    pub title: String,
+    pub state: String,
+    #[serde(skip)]
+    pub repo_name: String,
+    #[serde(skip)]
+    pub user_id: u64,
+    pub user: User,
+    pub labels: Vec<Label>,
+    pub created_at: DateTime<Utc>,
+    /// Only known right after fetching, it is not stored
+    #[serde(default)]
+    pub updated_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub closed_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub body: Option<String>,
+    /// The json the issue was deserialized from, see `--store-raw`
+    #[serde(skip)]
+    pub raw: Option<String>,
+    /// Set on sub-issues, e.g. `https://api.github.com/repos/owner/name/issues/1`
+    #[serde(default)]
+    pub parent_issue_url: Option<String>,
+    #[serde(default)]
+    pub reactions: Option<Reactions>,
+    /// `reopened` while an issue is open again, only known right after fetching
+    #[serde(default)]
+    pub state_reason: Option<String>,
+    #[serde(default)]
+    pub pull_request: Option<PullRequestRef>,
+    #[serde(default)]
+    pub milestone: Option<Milestone>,
+    #[serde(default)]
+    pub assignees: Vec<User>,
+    #[serde(default)]
+    pub locked: bool,
+    /// `off-topic`, `too heated`, `resolved` or `spam`, absent on unlocked issues
+    #[serde(default)]
+    pub active_lock_reason: Option<String>,
+    /// Written with `ih note`, never synced
+    #[serde(skip)]
+    pub note: Option<String>,
+    /// Only known right after fetching, it is not stored
+    #[serde(default)]
+    pub comments: u64,
+    /// Author of the first comment, see `update --with-first-response`
+    #[serde(skip)]
+    pub first_responder: Option<User>,
+}
+
+/// The reaction counts per type of an issue, absent on some endpoints.
+#[derive(Deserialize, Debug, Default, Clone, Copy)]
+pub struct Reactions {
+    #[serde(skip)]
+    pub issue_id: u64,
+    #[serde(rename = "+1")]
+    pub plus_one: u64,
+    #[serde(rename = "-1")]
+    pub minus_one: u64,
+    pub laugh: u64,
+    pub hooray: u64,
+    pub confused: u64,
+    pub heart: u64,
+    pub rocket: u64,
+    pub eyes: u64,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Milestone {
+    pub id: u64,
+    pub number: u64,
+    pub title: String,
+    pub state: String,
+    #[serde(skip)]
+    pub repo_name: String,
+    #[serde(default)]
+    pub due_on: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug)]
+pub struct IssueAssignee {
+    pub issue_id: u64,
+    pub user_id: u64,
+}
+
+/// Stored for locked issues only, the reason is optional on GitHub.
+#[derive(Debug)]
+pub struct IssueLock {
+    pub issue_id: u64,
+    pub reason: Option<String>,
+}
+
+/// Stored once `update --with-first-response` looked at the comments of an issue,
+/// `user_id` is `None` while nobody has commented.
+#[derive(Debug)]
+pub struct FirstResponse {
+    pub issue_id: u64,
+    pub user_id: Option<u64>,
+}
+
+/// An issue comment, only its author is used.
+#[derive(Deserialize, Debug)]
+pub struct Comment {
+    /// `None` for deleted accounts
+    pub user: Option<User>,
+}
+
+#[derive(Debug)]
+pub struct IssueMilestoneLink {
+    pub issue_id: u64,
+    pub milestone_id: u64,
+}
+
+/// How often an issue was seen reopened, see `Issue::record_reopen`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Reopens {
+    pub issue_id: u64,
+    pub reopened_count: u64,
+}
+
+/// A GitHub Discussion, which only the GraphQL API serves.
+#[derive(Debug, Default)]
+pub struct Discussion {
+    pub id: u64,
+    pub number: u64,
+    pub title: String,
+    pub repo_name: String,
+    /// `None` for deleted accounts
+    pub author: Option<String>,
+    pub category: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+implement_from_tuple!(
+    Discussion, (
+        id: u64 => |inner: &mut Discussion, value: DataValue| {
+            inner.id = value.u64().unwrap();
+        },
+        number: u64 => |inner: &mut Discussion, value: DataValue| {
+            inner.number = value.u64().unwrap();
+        },
+        title: String => |inner: &mut Discussion, value: DataValue| {
+            inner.title = value.utf8().unwrap().to_string();
+        },
+        repo_name: String => |inner: &mut Discussion, value: DataValue| {
+            inner.repo_name = value.utf8().unwrap().to_string();
+        },
+        author: String => |inner: &mut Discussion, value: DataValue| {
+            inner.author = value.utf8().map(|s| s.to_string());
+        },
+        category: String => |inner: &mut Discussion, value: DataValue| {
+            inner.category = value.utf8().map(|s| s.to_string());
+        },
+        created_at: NaiveDateTime => |inner: &mut Discussion, value: DataValue| {
+            inner.created_at = value.datetime().unwrap().and_utc();
+        }
+    )
+);
+
+/// Envelope of every GraphQL answer, `errors` may come with partial `data`.
+#[derive(Deserialize, Debug)]
+pub struct GraphQlResponse<T> {
+    pub data: Option<T>,
+    #[serde(default)]
+    pub errors: Vec<GraphQlError>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct GraphQlError {
+    pub message: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct DiscussionsData {
+    pub repository: Option<DiscussionsRepository>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct DiscussionsRepository {
+    pub discussions: DiscussionConnection,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct DiscussionConnection {
+    pub page_info: PageInfo,
+    pub nodes: Vec<DiscussionNode>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PageInfo {
+    pub has_next_page: bool,
+    pub end_cursor: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct DiscussionNode {
+    pub database_id: u64,
+    pub number: u64,
+    pub title: String,
+    pub created_at: DateTime<Utc>,
+    pub author: Option<Login>,
+    pub category: Option<CategoryName>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Login {
+    pub login: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct CategoryName {
+    pub name: String,
+}
+
+impl DiscussionNode {
+    pub fn into_discussion(self, repo_name: String) -> Discussion {
+        Discussion {
+            id: self.database_id,
+            number: self.number,
+            title: self.title,
+            repo_name,
+            author: self.author.map(|author| author.login),
+            category: self.category.map(|category| category.name),
+            created_at: self.created_at,
+        }
+    }
+}
+
+/// The body GitHub answers with instead of the requested resource, e.g. on a bad request.
+#[derive(Deserialize, Debug)]
+pub struct ApiError {
+    pub message: String,
+    pub documentation_url: Option<String>,
+}
+
+/// Present on items of the issues endpoint that are pull requests.
+#[derive(Deserialize, Debug, Default)]
+pub struct PullRequestRef {
+    pub url: String,
+    pub merged_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct PullRequestDetail {
+    pub merged: bool,
+    pub head: BranchRef,
+    pub base: BranchRef,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct BranchRef {
+    #[serde(rename = "ref")]
+    pub name: String,
+}
+
+#[derive(Debug, Default)]
+pub struct PullRequest {
+    pub issue: Issue,
+    pub merged: bool,
+    pub head: Option<String>,
+    pub base: Option<String>,
+}
+
+implement_from_tuple!(
+    PullRequest, (
+        id: u64 => |inner: &mut PullRequest, value: DataValue| {
+            inner.issue.id = value.u64().unwrap();
+        },
+        number: u64 => |inner: &mut PullRequest, value: DataValue| {
+            inner.issue.number = value.u64().unwrap();
+        },
+        title: String => |inner: &mut PullRequest, value: DataValue| {
+            inner.issue.title = value.utf8().unwrap().to_string();
+        },
+        state: String => |inner: &mut PullRequest, value: DataValue| {
+            inner.issue.state = value.utf8().unwrap().to_string();
+        },
+        repo_name: String => |inner: &mut PullRequest, value: DataValue| {
+            inner.issue.repo_name = value.utf8().unwrap().to_string();
+        },
+        user_id: u64 => |inner: &mut PullRequest, value: DataValue| {
+            inner.issue.user_id = value.u64().unwrap();
+        },
+        created_at: NaiveDateTime => |inner: &mut PullRequest, value: DataValue| {
+            inner.issue.created_at = value.datetime().unwrap().and_utc();
+        },
+        closed_at: NaiveDateTime => |inner: &mut PullRequest, value: DataValue| {
+            inner.issue.closed_at = value.datetime().map(|datetime| datetime.and_utc());
+        },
+        body: String => |inner: &mut PullRequest, value: DataValue| {
+            inner.issue.body = value.utf8().map(|s| s.to_string());
+        },
+        raw: String => |inner: &mut PullRequest, value: DataValue| {
+            inner.issue.raw = value.utf8().map(|s| s.to_string());
+        },
+        parent_issue_url: String => |inner: &mut PullRequest, value: DataValue| {
+            inner.issue.parent_issue_url = value.utf8().map(|s| s.to_string());
+        },
+        merged: bool => |inner: &mut PullRequest, value: DataValue| {
+            inner.merged = value.bool().unwrap();
+        },
+        head: String => |inner: &mut PullRequest, value: DataValue| {
+            inner.head = value.utf8().map(|s| s.to_string());
+        },
+        base: String => |inner: &mut PullRequest, value: DataValue| {
+            inner.base = value.utf8().map(|s| s.to_string());
+        }
+    )
+);
+
+implement_from_tuple!(
+    Issue, (
+        id: u64 => |inner: &mut Issue, value: DataValue| {
+            inner.id = value.u64().unwrap();
+        },
+        number: u64 => |inner: &mut Issue, value: DataValue| {
+            inner.number = value.u64().unwrap();
+        },
+        title: String => |inner: &mut Issue, value: DataValue| {
+            inner.title = value.utf8().unwrap().to_string();
+        },
+        state: String => |inner: &mut Issue, value: DataValue| {
+            inner.state = value.utf8().unwrap().to_string();
+        },
+        repo_name: String => |inner: &mut Issue, value: DataValue| {
+            inner.repo_name = value.utf8().unwrap().to_string();
+        },
+        user_id: u64 => |inner: &mut Issue, value: DataValue| {
+            inner.user_id = value.u64().unwrap();
+        },
+        created_at: NaiveDateTime => |inner: &mut Issue, value: DataValue| {
+            inner.created_at = value.datetime().unwrap().and_utc();
+        },
+        closed_at: NaiveDateTime => |inner: &mut Issue, value: DataValue| {
+            inner.closed_at = value.datetime().map(|datetime| datetime.and_utc());
+        },
+        body: String => |inner: &mut Issue, value: DataValue| {
+            inner.body = value.utf8().map(|s| s.to_string());
+        },
+        raw: String => |inner: &mut Issue, value: DataValue| {
+            inner.raw = value.utf8().map(|s| s.to_string());
+        },
+        parent_issue_url: String => |inner: &mut Issue, value: DataValue| {
+            inner.parent_issue_url = value.utf8().map(|s| s.to_string());
+        }
+    )
+);
+
+#[derive(Debug, Default)]
+pub struct ETag {
+    pub url: String,
+    pub etag: String,
+    pub oldest_created: DateTime<Utc>,
+}
+
+implement_from_tuple!(
+    ETag, (
+        url: String => |inner: &mut ETag, value: DataValue| {
+            inner.url = value.utf8().unwrap().to_string();
+        },
+        etag: String => |inner: &mut ETag, value: DataValue| {
+            inner.etag = value.utf8().unwrap().to_string();
+        },
+        oldest_created: NaiveDateTime => |inner: &mut ETag, value: DataValue| {
+            inner.oldest_created = value.datetime().unwrap().and_utc();
+        }
+    )
+);
+
+/// How far an interrupted `update` got in a repo, removed once the repo is synced.
+#[derive(Debug, Default)]
+pub struct Checkpoint {
+    pub repo_name: String,
+    /// The `--sort-by` of the interrupted run, pages of another order do not line up
+    pub sort_by: String,
+    /// The first page not yet stored
+    pub page: usize,
+    pub oldest_created: Option<DateTime<Utc>>,
+}
+
+implement_from_tuple!(
+    Checkpoint, (
+        repo_name: String => |inner: &mut Checkpoint, value: DataValue| {
+            inner.repo_name = value.utf8().unwrap().to_string();
+        },
+        sort_by: String => |inner: &mut Checkpoint, value: DataValue| {
+            inner.sort_by = value.utf8().unwrap().to_string();
+        },
+        page: u64 => |inner: &mut Checkpoint, value: DataValue| {
+            inner.page = value.u64().unwrap() as usize;
+        },
+        oldest_created: NaiveDateTime => |inner: &mut Checkpoint, value: DataValue| {
+            inner.oldest_created = value.datetime().map(|datetime| datetime.and_utc());
+        }
+    )
+);
+
+#[derive(Debug)]
+pub struct IssueLabelLink {
+    pub issue_id: u64,
+    pub label_id: u64,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct User {
+    pub id: u64,
+    pub login: String,
+}
+
+implement_from_tuple!(
+    User, (
+        id: u64 => |inner: &mut User, value: DataValue| {
+            inner.id = value.u64().unwrap();
+        },
+        login: String => |inner: &mut User, value: DataValue| {
+            inner.login = value.utf8().unwrap().to_string();
+        }
+    )
+);
+
+#[derive(Deserialize, Hash, Debug, Default, PartialEq, Eq)]
+pub struct Label {
+    pub id: u64,
+    pub name: String,
+    pub description: Option<String>,
+    /// Hex without the leading `#`, e.g. `d73a4a`
+    #[serde(default)]
+    pub color: Option<String>,
+}
+
+implement_from_tuple!(
+    Label, (
+        id: u64 => |inner: &mut Label, value: DataValue| {
+            inner.id = value.u64().unwrap();
+        },
+        name: String => |inner: &mut Label, value: DataValue| {
+            inner.name = value.utf8().unwrap().to_string();
+        },
+        description: String => |inner: &mut Label, value: DataValue| {
+            inner.description = value.utf8().map(|s| s.to_string());
+        },
+        color: String => |inner: &mut Label, value: DataValue| {
+            inner.color = value.utf8().map(|s| s.to_string());
+        }
+    )
+);
+
+#[derive(Deserialize, Parser, Debug, Default, Clone)]
+pub struct Repo {
+    #[clap(name = "owner", long)]
+    pub owner_name: String,
+    #[clap(long)]
+    pub name: String,
+    #[clap(skip)]
+    pub default_branch: Option<String>,
+    /// When the last update of the repo succeeded
+    #[clap(skip)]
+    pub last_updated: Option<DateTime<Utc>>,
+    /// Archived repos keep their issues but are skipped by `update`
+    #[clap(skip)]
+    #[serde(default)]
+    pub archived: bool,
+    /// Page the first sync continues from after `--first-sync-limit` stopped it
+    #[clap(skip)]
+    #[serde(default)]
+    pub first_sync_page: Option<usize>,
+    /// Stored apart in `repo_topics`, see `Repo::load_topics`
+    #[clap(skip)]
+    #[serde(default)]
+    pub topics: Vec<String>,
+    /// Environment variable holding the token the repo is synced with, `GITHUB_TOKEN` when unset
+    #[clap(skip)]
+    #[serde(default)]
+    pub token_env: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct RepoTopic {
+    pub repo_name: String,
+    pub topic: String,
+}
+
+/// Item of the `/issues` and `/search/issues` endpoints, which name the repository of each issue.
+#[derive(Deserialize, Debug)]
+pub struct CrossRepoIssue {
+    #[serde(flatten)]
+    pub issue: Issue,
+    pub repository_url: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct SearchResult {
+    pub total_count: usize,
+    pub items: Vec<CrossRepoIssue>,
+}
+
+/// Response of the `/user` endpoint, the owner of the token.
+#[derive(Deserialize, Debug)]
+pub struct AuthenticatedUser {
+    pub login: String,
+    pub name: Option<String>,
+    /// Only present for the token's own account
+    pub plan: Option<Plan>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Plan {
+    pub name: String,
+}
+
+/// Response of the `/rate_limit` endpoint.
+#[derive(Deserialize, Debug)]
+pub struct RateLimits {
+    pub resources: RateLimitResources,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct RateLimitResources {
+    pub core: RateLimit,
+    pub search: RateLimit,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct RateLimit {
+    pub limit: u64,
+    pub remaining: u64,
+    #[serde(with = "chrono::serde::ts_seconds")]
+    pub reset: DateTime<Utc>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct RepoMetadata {
+    pub full_name: String,
+    pub default_branch: Option<String>,
+    #[serde(default)]
+    pub topics: Vec<String>,
+}
+
+/// A reference of the database pointing at a missing row.
+#[derive(Debug)]
+pub enum Problem {
+    MissingUser {
+        table: &'static str,
+        issue_id: u64,
+        user_id: u64,
+    },
+    MissingLabel(IssueLabelLink),
+    MissingIssue(IssueLabelLink),
+}
+
+/// What happened to an issue since the snapshot taken by `ih snapshot`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Change {
+    Added,
+    Closed,
+    Reopened,
+    Relabeled,
+}
+
+impl Change {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Change::Added => "added",
+            Change::Closed => "closed",
+            Change::Reopened => "reopened",
+            Change::Relabeled => "relabeled",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct IssueChange {
+    pub change: Change,
+    pub repo_name: String,
+    pub number: u64,
+    pub title: String,
+    /// The labels before and after for `Relabeled`
+    pub labels: Option<(Vec<String>, Vec<String>)>,
+}
+
+impl IssueChange {
+    pub fn to_json(&self) -> Value {
+        let (labels_before, labels_after) = self.labels.clone().unzip();
+
+        serde_json::json!({
+            "change": self.change.as_str(),
+            "repo_name": self.repo_name,
+            "number": self.number,
+            "title": self.title,
+            "labels_before": labels_before,
+            "labels_after": labels_after,
+        })
+    }
+}
+
+impl Repo {
+    pub fn full_name(&self) -> String {
+        format!("{}/{}", self.owner_name, self.name)
+    }
+
+    pub fn url(&self) -> String {
+        format!("https://github.com/{}", self.full_name())
+    }
+
+    pub fn to_json(&self) -> Value {
+        serde_json::json!({
+            "owner": self.owner_name,
+            "name": self.name,
+            "full_name": self.full_name(),
+            "url": self.url(),
+            "default_branch": self.default_branch,
+            "last_updated": self.last_updated.map(|last_updated| last_updated.to_rfc3339()),
+            "archived": self.archived,
+            "topics": self.topics,
+            "token_env": self.token_env,
+        })
+    }
+
+    pub fn load_topics(&mut self, database: &SqlBase) -> Result<(), Error> {
+        self.topics.clear();
+        for tuple in database.run(format!(
+            "select topic from repo_topics where repo_name = '{}'",
+            escape_sql_string(&self.full_name())
+        ))? {
+            self.topics
+                .push(tuple?.values[0].utf8().unwrap_or_default().to_string());
+        }
+        self.topics.sort();
+
+        Ok(())
+    }
+}
+
+impl FromStr for Repo {
+    type Err = Error;
+
+    fn from_str(full_name: &str) -> Result<Self, Self::Err> {
+        match full_name.trim().split_once('/') {
+            Some((owner_name, name))
+                if !owner_name.is_empty() && !name.is_empty() && !name.contains('/') =>
+            {
+                Ok(Repo {
+                    owner_name: owner_name.to_string(),
+                    name: name.to_string(),
+                    ..Default::default()
+                })
+            }
+            _ => Err(Error::Parse(format!(
+                "'{}' is not in the form of owner/name",
+                full_name
+            ))),
+        }
+    }
+}
+
+implement_from_tuple!(
+    Repo, (
+        owner_name: String => |inner: &mut Repo, value: DataValue| {
+            inner.owner_name = value.utf8().unwrap().to_string();
+        },
+        name: String => |inner: &mut Repo, value: DataValue| {
+            inner.name = value.utf8().unwrap().to_string();
+        },
+        default_branch: String => |inner: &mut Repo, value: DataValue| {
+            inner.default_branch = value.utf8().map(|s| s.to_string());
+        },
+        last_updated: NaiveDateTime => |inner: &mut Repo, value: DataValue| {
+            inner.last_updated = value.datetime().map(|datetime| datetime.and_utc());
+        },
+        archived: bool => |inner: &mut Repo, value: DataValue| {
+            inner.archived = value.bool().unwrap_or_default();
+        },
+        first_sync_page: u64 => |inner: &mut Repo, value: DataValue| {
+            inner.first_sync_page = value.u64().map(|page| page as usize);
+        },
+        token_env: String => |inner: &mut Repo, value: DataValue| {
+            inner.token_env = value.utf8().map(|s| s.to_string());
+        }
+    )
+);
+
+impl Issue {
+    /// Deserializes an issue of the GitHub api, keeping its json when `store_raw`.
+    pub fn from_json(value: Value, store_raw: bool) -> Result<Self, Error> {
+        let raw = store_raw.then(|| value.to_string());
+        let mut issue =
+            serde_json::from_value::<Issue>(value).map_err(|err| Error::Parse(err.to_string()))?;
+        issue.raw = raw;
+
+        Ok(issue)
+    }
+
+    /// Builds the json object of the issue with only the given fields.
+    pub fn to_json(&self, fields: &[&str]) -> Value {
+        let mut object = Map::new();
+
+        for field in fields {
+            let value = match *field {
+                "id" => Value::from(self.id),
+                "number" => Value::from(self.number),
+                "repo_name" => Value::from(self.repo_name.as_str()),
+                "title" => Value::from(self.title.as_str()),
+                "state" => Value::from(self.state.as_str()),
+                "user" => Value::from(self.user.login.as_str()),
+                "labels" => Value::from(
+                    self.labels
+                        .iter()
+                        .map(|label| label.name.as_str())
+                        .collect::<Vec<_>>(),
+                ),
+                "created_at" => Value::from(self.created_at.to_rfc3339()),
+                "closed_at" => Value::from(self.closed_at.map(|closed_at| closed_at.to_rfc3339())),
+                "parent" => Value::from(
+                    self.parent()
+                        .map(|(repo_name, number)| format!("{}#{}", repo_name, number)),
+                ),
+                "note" => Value::from(self.note.as_deref()),
+                "lock_reason" => Value::from(self.active_lock_reason.as_deref()),
+                "first_responder" => Value::from(
+                    self.first_responder
+                        .as_ref()
+                        .map(|first_responder| first_responder.login.as_str()),
+                ),
+                _ => continue,
+            };
+            object.insert(field.to_string(), value);
+        }
+
+        Value::Object(object)
+    }
+
+    /// The repo and number of the parent issue, `None` for top-level issues.
+    pub fn parent(&self) -> Option<(String, u64)> {
+        let path = self
+            .parent_issue_url
+            .as_deref()?
+            .strip_prefix("https://api.github.com/repos/")?;
+        let (repo_name, number) = path.rsplit_once("/issues/")?;
+
+        Some((repo_name.to_string(), number.parse().ok()?))
+    }
+
+    pub(crate) fn insert_user_and_labels(&self, database: &SqlBase) -> Result<(), Error> {
+        self.user.insert(database)?;
+        // Labels removed upstream would otherwise stay linked forever
+        database
+            .run(format!(
+                "delete from issue_labels where issue_id = {};",
+                self.id
+            ))?
+            .done()?;
+        for label in &self.labels {
+            IssueLabelLink {
+                issue_id: self.id,
+                label_id: label.id,
+            }
+            .insert(database)?;
+            label.insert(database)?;
+        }
+        // An issue has at most one milestone, which may have been removed upstream
+        database
+            .run(format!(
+                "delete from issue_milestones where issue_id = {};",
+                self.id
+            ))?
+            .done()?;
+        if let Some(milestone) = &self.milestone {
+            Milestone {
+                repo_name: self.repo_name.clone(),
+                ..milestone.clone()
+            }
+            .insert(database)?;
+            IssueMilestoneLink {
+                issue_id: self.id,
+                milestone_id: milestone.id,
+            }
+            .insert(database)?;
+        }
+        database
+            .run(format!(
+                "delete from assignees where issue_id = {};",
+                self.id
+            ))?
+            .done()?;
+        for assignee in &self.assignees {
+            assignee.insert(database)?;
+            IssueAssignee {
+                issue_id: self.id,
+                user_id: assignee.id,
+            }
+            .insert(database)?;
+        }
+        database
+            .run(format!("delete from locks where issue_id = {};", self.id))?
+            .done()?;
+        if self.locked {
+            IssueLock {
+                issue_id: self.id,
+                reason: self.active_lock_reason.clone(),
+            }
+            .insert(database)?;
+        }
+        if let Some(reactions) = &self.reactions {
+            Reactions {
+                issue_id: self.id,
+                ..*reactions
+            }
+            .insert(database)?;
+        }
+
+        Ok(())
+    }
+
+    /// Counts a reopen when the stored copy of the issue in `table` is closed and this one
+    /// is open. Only transitions between syncs are seen, so an issue closed and reopened
+    /// in between is missed; `state_reason` at least tells an issue was reopened once.
+    pub(crate) fn record_reopen(&self, table: &str, database: &SqlBase) -> Result<(), Error> {
+        let stored_state = database
+            .run(format!(
+                "select state from {} where id = {}",
+                table, self.id
+            ))?
+            .next()
+            .transpose()?
+            .and_then(|tuple| tuple.values[0].utf8().map(str::to_string));
+        let stored_count = database
+            .run(format!(
+                "select reopened_count from reopens where issue_id = {}",
+                self.id
+            ))?
+            .next()
+            .transpose()?
+            .and_then(|tuple| tuple.values[0].i32())
+            .unwrap_or_default() as u64;
+
+        let mut reopened_count = stored_count;
+        if stored_state.as_deref() == Some("closed") && self.state == "open" {
+            reopened_count += 1;
+        }
+        if self.state_reason.as_deref() == Some("reopened") {
+            reopened_count = reopened_count.max(1);
+        }
+        if reopened_count != stored_count {
+            Reopens {
+                issue_id: self.id,
+                reopened_count,
+            }
+            .insert(database)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn load_user(&mut self, database: &SqlBase) -> Result<(), Error> {
+        let mut iter = database.run(format!("select * from users where id = {}", self.user_id))?;
+        let schema = iter.schema().clone();
+        let tuple = iter
+            .next()
+            .transpose()?
+            .ok_or_else(|| Error::NotFound(format!("user: {}", self.user_id)))?;
+        self.user = User::from((&schema, tuple));
+
+        Ok(())
+    }
+
+    pub fn load_lock(&mut self, database: &SqlBase) -> Result<(), Error> {
+        let lock = database
+            .run(format!(
+                "select reason from locks where issue_id = {}",
+                self.id
+            ))?
+            .next()
+            .transpose()?;
+        self.locked = lock.is_some();
+        self.active_lock_reason = lock.and_then(|tuple| tuple.values[0].utf8().map(str::to_string));
+
+        Ok(())
+    }
+
+    /// Swaps the author for its pseudonym and drops the body, raw json and note.
+    pub fn anonymize(&mut self, pseudonyms: &HashMap<u64, String>) {
+        self.user.login = pseudonyms
+            .get(&self.user.id)
+            .cloned()
+            .unwrap_or_else(|| "ghost".to_string());
+        self.user.id = 0;
+        if let Some(first_responder) = &mut self.first_responder {
+            first_responder.login = pseudonyms
+                .get(&first_responder.id)
+                .cloned()
+                .unwrap_or_else(|| "ghost".to_string());
+            first_responder.id = 0;
+        }
+        self.body = None;
+        self.raw = None;
+        self.note = None;
+    }
+
+    /// The state with the lock, e.g. `open, locked: spam`.
+    pub fn state_with_lock(&self) -> String {
+        match (self.locked, &self.active_lock_reason) {
+            (false, _) => self.state.clone(),
+            (true, None) => format!("{}, locked", self.state),
+            (true, Some(reason)) => format!("{}, locked: {}", self.state, reason),
+        }
+    }
+
+    pub fn load_note(&mut self, database: &SqlBase) -> Result<(), Error> {
+        self.note = database
+            .run(format!(
+                "select note from notes where issue_id = {}",
+                self.id
+            ))?
+            .next()
+            .transpose()?
+            .and_then(|tuple| tuple.values[0].utf8().map(str::to_string));
+
+        Ok(())
+    }
+
+    pub fn load_first_responder(&mut self, database: &SqlBase) -> Result<(), Error> {
+        let mut iter = database.run(format!(
+            "select u.* from users u inner join first_responses f on u.id = f.user_id where f.issue_id = {}",
+            self.id
+        ))?;
+        let schema = iter.schema().clone();
+        self.first_responder = iter
+            .next()
+            .transpose()?
+            .map(|tuple| User::from((&schema, tuple)));
+
+        Ok(())
+    }
+
+    pub fn load_labels(&mut self, database: &SqlBase) -> Result<(), Error> {
+        let iter = database.run(format!("SELECT l.* FROM labels l INNER JOIN issue_labels il ON l.id = il.label_id WHERE il.issue_id = {};", self.id))?;
+        let schema = iter.schema().clone();
+
+        self.labels.clear();
+        for tuple in iter {
+            self.labels.push(Label::from((&schema, tuple?)));
+        }
+
+        Ok(())
+    }
+}
+
+impl ETag {
+    pub fn load(database: &SqlBase, url: &str) -> Result<Option<ETag>, Error> {
+        let mut iter = database.run(format!(
+            "select * from etags where url = '{}'",
+            escape_sql_string(url)
+        ))?;
+        let schema = iter.schema().clone();
+
+        Ok(iter
+            .next()
+            .transpose()?
+            .map(|tuple| ETag::from((&schema, tuple))))
+    }
+}
+
+impl Checkpoint {
+    pub fn load(database: &SqlBase, repo_name: &str) -> Result<Option<Checkpoint>, Error> {
+        let mut iter = database.run(format!(
+            "select * from checkpoints where repo_name = '{}'",
+            escape_sql_string(repo_name)
+        ))?;
+        let schema = iter.schema().clone();
+
+        Ok(iter
+            .next()
+            .transpose()?
+            .map(|tuple| Checkpoint::from((&schema, tuple))))
+    }
+}
+
+pub const ISSUE_FIELDS: [&str; 13] = [
+    "id",
+    "number",
+    "repo_name",
+    "title",
+    "state",
+    "user",
+    "labels",
+    "created_at",
+    "closed_at",
+    "parent",
+    "note",
+    "lock_reason",
+    "first_responder",
+];
+
+pub fn parse_fields(fields: &[String]) -> Result<Vec<&str>, Error> {
+    fields
+        .iter()
+        .map(|field| {
+            ISSUE_FIELDS
+                .iter()
+                .find(|known| **known == field.trim())
+                .copied()
+                .ok_or_else(|| {
+                    Error::Parse(format!(
+                        "invalid field: '{}', expected some of: {}",
+                        field,
+                        ISSUE_FIELDS.join(", ")
+                    ))
+                })
+        })
+        .collect()
+}